@@ -0,0 +1,94 @@
+// average.rs
+//
+// averaging colorings: instead of how long the orbit took to escape, color by
+// the average of some function of z along it. the averages are smoothed
+// between the last two iterations the same way the iteration count is
+use simd::Vector;
+use num::{Float, Zero, cast};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Average {
+    // 0.5 + 0.5*sin(density * arg z), the density is how many stripes go
+    // around
+    Stripe(f64),
+    // where |z| falls between ||z_prev^d| - |c|| and |z_prev^d| + |c|, the
+    // bounds the triangle inequality puts on it
+    Triangle,
+}
+
+// stripe, stripe:<density> or triangle
+impl ::std::str::FromStr for Average {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Average, String> {
+        let mut parts = s.splitn(2, ':');
+        let name = parts.next().unwrap();
+        let arg = parts.next();
+        match (name, arg) {
+            ("stripe", None) => Ok(Average::Stripe(5.0)),
+            ("stripe", Some(x)) => x.trim().parse::<f64>()
+                .map(Average::Stripe)
+                .map_err(|_| format!("bad stripe density {}", x)),
+            ("triangle", None) => Ok(Average::Triangle),
+            _ => Err(format!("unknown average {}", s)),
+        }
+    }
+}
+
+// smooths between the average with and without the term of the iteration
+// that escaped, like smooth_iter does between iteration counts. sum and count
+// include that term, last is the term itself, and mag2 the |z|^2 it escaped
+// at
+pub(crate) fn smooth_average(sum: f64, last: f64, count: f64, mag2: f64, bailout: f64, degree: f64) -> f64 {
+    let with = sum / count;
+    let without = if count > 1.0 { (sum - last) / (count - 1.0) } else { with };
+    // 1 when z only just escaped, 0 when it was only just in the iteration
+    // before
+    let d = 1.0 - ((mag2.ln() / 2.0) / bailout.ln()).ln() / degree.ln();
+    let d = d.max(0.0).min(1.0);
+    d * with + (1.0 - d) * without
+}
+
+// an average with its constants splatted, for the kernel
+pub(crate) struct VectorAverage<V: Vector> {
+    average: Average,
+    density: V::Elem,
+    degree: V::Elem,
+}
+
+impl<V: Vector> VectorAverage<V> {
+    pub fn new(average: Average, degree: f64) -> VectorAverage<V> {
+        let density = match average {
+            Average::Stripe(density) => density,
+            Average::Triangle => 0.0,
+        };
+        VectorAverage { average: average, density: cast(density).unwrap(), degree: cast(degree).unwrap() }
+    }
+
+    // what z adds to the average. prev_mag2 is the |z|^2 it was iterated
+    // from, and c_mod is |c|
+    #[inline(always)]
+    pub fn term(&self, zr: V, zi: V, prev_mag2: V, c_mod: V) -> V {
+        let half: V::Elem = cast(0.5).unwrap();
+        match self.average {
+            Average::Stripe(_) => {
+                let density = self.density;
+                V::map2(zr, zi, |x, y| (half * (density * y.atan2(x)).sin() + half, y)).0
+            },
+            Average::Triangle => {
+                let two: V::Elem = cast(2).unwrap();
+                // |z_prev^d|
+                let prev = if self.degree == two {
+                    prev_mag2
+                } else {
+                    let power = self.degree * half;
+                    V::map2(prev_mag2, prev_mag2, |x, _| (x.powf(power), x)).0
+                };
+                let z = zr.mul_add(zr, zi * zi).sqrt();
+                let low = (prev - c_mod).abs();
+                let range = prev + c_mod - low;
+                // the bounds meet when c or z_prev is 0
+                V::map2(z - low, range, |x, r| (if r > V::Elem::zero() { x / r } else { r }, r)).0
+            },
+        }
+    }
+}
@@ -0,0 +1,99 @@
+// backend.rs
+use FractalCfg;
+use render::RenderRows;
+use fractal::escape_time;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use simd::{F32x4, F32x8, F32x8Fma, F64x2, F64x4, F64x4Fma};
+
+// instruction sets the escape time kernel is compiled for, slowest first
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Scalar,
+    Sse2,
+    Avx,
+    Avx2Fma,
+}
+
+impl Backend {
+    pub fn all() -> Vec<Backend> {
+        vec![Backend::Scalar, Backend::Sse2, Backend::Avx, Backend::Avx2Fma]
+    }
+
+    // every backend the cpu we're running on can execute, slowest first
+    pub fn available() -> Vec<Backend> {
+        Backend::all().into_iter().filter(|b| b.is_supported()).collect()
+    }
+
+    // fastest backend the cpu supports
+    pub fn detect() -> Backend {
+        *Backend::available().last().unwrap()
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub fn is_supported(&self) -> bool {
+        match *self {
+            Backend::Scalar  => true,
+            Backend::Sse2    => is_x86_feature_detected!("sse2"),
+            Backend::Avx     => is_x86_feature_detected!("avx"),
+            Backend::Avx2Fma => is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma"),
+        }
+    }
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    pub fn is_supported(&self) -> bool {
+        *self == Backend::Scalar
+    }
+
+    // renders whole rows into out, starting at row first_row of the image
+    pub fn render(&self, cfg: &FractalCfg, first_row: usize, out: &mut RenderRows) {
+        if !self.is_supported() {
+            panic!("backend {:?} is not supported by this cpu", self);
+        }
+        let double = cfg.use_double();
+        match *self {
+            Backend::Scalar  => if double {
+                escape_time::<f64>(cfg, first_row, out)
+            } else {
+                escape_time::<f32>(cfg, first_row, out)
+            },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Sse2    => unsafe { render_sse2(cfg, first_row, out, double) },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Avx     => unsafe { render_avx(cfg, first_row, out, double) },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Backend::Avx2Fma => unsafe { render_avx2_fma(cfg, first_row, out, double) },
+            #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn render_sse2(cfg: &FractalCfg, first_row: usize, out: &mut RenderRows, double: bool) {
+    if double {
+        escape_time::<F64x2>(cfg, first_row, out)
+    } else {
+        escape_time::<F32x4>(cfg, first_row, out)
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx")]
+unsafe fn render_avx(cfg: &FractalCfg, first_row: usize, out: &mut RenderRows, double: bool) {
+    if double {
+        escape_time::<F64x4>(cfg, first_row, out)
+    } else {
+        escape_time::<F32x8>(cfg, first_row, out)
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn render_avx2_fma(cfg: &FractalCfg, first_row: usize, out: &mut RenderRows, double: bool) {
+    if double {
+        escape_time::<F64x4Fma>(cfg, first_row, out)
+    } else {
+        escape_time::<F32x8Fma>(cfg, first_row, out)
+    }
+}
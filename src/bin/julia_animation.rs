@@ -32,6 +32,11 @@ pub fn main() {
              .default_value("256")
              .long("iter")
              )
+        .arg(Arg::with_name("bailout")
+             .help("escape radius, large for smooth colors and small for some traps")
+             .default_value("256")
+             .long("bailout")
+             )
         .arg(Arg::with_name("r")
              .help("real value of center point")
              .default_value("0")
@@ -96,11 +101,111 @@ pub fn main() {
              .long("cmap")
              .default_value("hot")
              )
+        .arg(Arg::with_name("negative_colormap")
+             .help("colormap for negative values, like stable points of --formula lyapunov")
+             .long("neg-cmap")
+             .default_value("cosine")
+             )
+        .arg(Arg::with_name("interior")
+             .help("coloring of the inside: black, period (of the cycle the orbit settles into) or multiplier (of that cycle)")
+             .long("interior")
+             .default_value("black")
+             )
+        .arg(Arg::with_name("interior_colormap")
+             .help("colormap for --interior period and multiplier")
+             .long("interior-cmap")
+             .default_value("hsv")
+             )
+        .arg(Arg::with_name("exterior")
+             .help("shading of the outside by the angle z escaped at: plain, binary (decomposition, by the sign of im(z)) or field-lines (along the external rays)")
+             .long("exterior")
+             .default_value("plain")
+             )
+        .arg(Arg::with_name("relief")
+             .help("light the image like a height map: <angle>,<height>,<ambient>, the direction the light comes from in degrees, how steep the slopes are and the brightness in the shadows (like 45,1,0.25)")
+             .long("relief")
+             .takes_value(true)
+             )
         .arg(Arg::with_name("points")
              .help("file containing points per frame")
              .long("points")
              .takes_value(true)
              )
+        .arg(Arg::with_name("precision")
+             .help("float precision of the kernel: auto, single, double or perturbation")
+             .long("precision")
+             .default_value("auto")
+             )
+        .arg(Arg::with_name("no_series")
+             .help("don't skip iterations with series approximation in deep zooms")
+             .long("no-series")
+             .takes_value(false)
+             )
+        .arg(Arg::with_name("references")
+             .help("reference orbits to try in deep zooms before giving up on glitched pixels")
+             .long("references")
+             .default_value("32")
+             )
+        .arg(Arg::with_name("no_bulb_check")
+             .help("iterate the main cardioid and period 2 bulb instead of skipping them")
+             .long("no-bulb-check")
+             .takes_value(false)
+             )
+        .arg(Arg::with_name("no_periodicity")
+             .help("don't stop iterating orbits that have become periodic")
+             .long("no-periodicity")
+             .takes_value(false)
+             )
+        .arg(Arg::with_name("threads")
+             .help("number of render threads, 0 for one per core")
+             .long("threads")
+             .default_value("0")
+             )
+        .arg(Arg::with_name("formula")
+             .help("formula to iterate: mandelbrot, multibrot:<power> for z^power + c, tricorn, multicorn:<power> for conj(z)^power + c, burningship, celtic, buffalo, perpendicular, phoenix:<p> for z^2 + c + p*z_(n-1), newton, nova:<relaxation>, lyapunov, buddhabrot, iim or miim:<hits> (julia sets by inverse iteration, plain or stopping at pixels hit that often)")
+             .long("formula")
+             .default_value("mandelbrot")
+             )
+        .arg(Arg::with_name("distance")
+             .help("color by estimated distance to the set (in pixels) instead of iterations")
+             .long("distance")
+             )
+        .arg(Arg::with_name("trap")
+             .help("color by distance to an orbit trap instead of iterations: point:<c>, circle:<c>,<radius>, cross:<c> or line:<c>,<angle>")
+             .long("trap")
+             .takes_value(true)
+             )
+        .arg(Arg::with_name("average")
+             .help("color by an average along the orbit instead of iterations: stripe:<density> or triangle")
+             .long("average")
+             .takes_value(true)
+             )
+        .arg(Arg::with_name("polynomial")
+             .help("polynomial for --formula newton and nova, as coefficients from the highest power down (coefs:1,0,0,-1 is z^3-1) or as roots (roots:1,i,-i)")
+             .long("poly")
+             .default_value("coefs:1,0,0,-1")
+             )
+        .arg(Arg::with_name("sequence")
+             .help("sequence of A and B for --formula lyapunov")
+             .long("seq")
+             .default_value("AB")
+             )
+        .arg(Arg::with_name("samples")
+             .help("random points per pixel for --formula buddhabrot and iim")
+             .long("samples")
+             .default_value("16")
+             )
+        .arg(Arg::with_name("seed")
+             .help("random seed for --formula buddhabrot and iim")
+             .long("seed")
+             .default_value("0")
+             )
+        .arg(Arg::with_name("nebula")
+             .help("iteration limits of the red, green and blue channels, for a nebulabrot with --formula buddhabrot (like 5000,500,50)")
+             .long("nebula")
+             .takes_value(true)
+             .use_delimiter(true)
+             )
         .arg(Arg::with_name("downsample")
              .help("downsample result from 4x resolution")
              .long("downsample")
@@ -108,7 +213,13 @@ pub fn main() {
              )
         .get_matches();
     
-    let cfg      = FractalCfg { julia: true, .. FractalCfg::from_matches(&matches) };
+    let cfg = match FractalCfg::from_matches(&matches) {
+        Ok(cfg) => FractalCfg { julia: true, .. cfg },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
     let output   = matches.value_of("output").unwrap();
 
     let points: Vec<_> = 
@@ -150,6 +261,7 @@ fn julia_animation(cfg: &FractalCfg, points: &Vec<(f64,f64)>, output: &str) {
             let new_cfg = FractalCfg {
                 cr: cr,
                 ci: ci,
+                threads: 0,
                 .. cfg.clone()
             };
             let filename = format!("frame_{}.png", i);
@@ -157,13 +269,22 @@ fn julia_animation(cfg: &FractalCfg, points: &Vec<(f64,f64)>, output: &str) {
         })
         .collect();
 
-    configs.into_par_iter()
+    let render_frames = || configs.into_par_iter()
         .for_each(|(new_cfg, filename)| {
             print!("rendering {}...", filename);
             std::io::stdout().flush().unwrap();
-            write_fractal(&new_cfg, Path::new(output).join(filename).to_str().unwrap(), false, true).unwrap();
+            write_fractal(&new_cfg, Path::new(output).join(filename).to_str().unwrap(), None, true).unwrap();
             println!("done");
         });
+    // frames render in parallel with each other, so the thread count goes on
+    // one pool for all of them instead of on every frame
+    if cfg.threads == 0 {
+        render_frames();
+    } else {
+        rayon::ThreadPoolBuilder::new().num_threads(cfg.threads).build()
+            .expect("failed to create thread pool")
+            .install(render_frames);
+    }
 
     println!("ffmpeg -framerate 60 -y -i {}/frame_%d.png {}.mp4", output, output);
     std::process::Command::new("ffmpeg")
@@ -0,0 +1,153 @@
+// buddhabrot.rs
+//
+// buddhabrot: instead of coloring c by how its orbit behaves, pick lots of
+// random c, and for the ones that escape, count every point of their orbit
+// in the pixel it lands on. the image is how often each pixel was visited.
+//
+// with three iteration limits (FractalCfg::channel_iterations) this is the
+// nebulabrot: channel k only counts orbits that escape within limit k, and
+// the channels end up as red, green and blue
+use FractalCfg;
+use fractal::PixelGrid;
+use rand::{Rng, SeedableRng, XorShiftRng};
+use rayon::prelude::*;
+
+// samples drawn from each rng. the split doesn't depend on the number of
+// threads, so neither does the image
+pub(crate) const CHUNK_SAMPLES: usize = 1 << 14;
+
+// visit counts, one buffer per channel, rows top to bottom like everything
+// else
+pub struct Density {
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl Density {
+    // all channels added up
+    pub fn total(&self) -> Vec<f32> {
+        let mut out = vec![0f32; self.channels[0].len()];
+        for channel in self.channels.iter() {
+            for (x, y) in out.iter_mut().zip(channel.iter()) {
+                *x += *y;
+            }
+        }
+        out
+    }
+}
+
+// splitmix64, to turn (seed, chunk) into well mixed rng states. xorshift
+// seeded with nearby numbers gives nearby numbers for a while
+fn mix(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9e3779b97f4a7c15);
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+pub(crate) fn chunk_rng(seed: u64, chunk: usize) -> XorShiftRng {
+    let a = mix(seed ^ mix(chunk as u64));
+    let b = mix(a);
+    // can't be all zeros, and b | 1 isn't
+    XorShiftRng::from_seed([a as u32, (a >> 32) as u32, (b | 1) as u32, (b >> 32) as u32])
+}
+
+// c in the main cardioid or the period 2 bulb never escapes, and those are
+// most of the set
+fn in_main_bulbs(cr: f64, ci: f64) -> bool {
+    let q = (cr - 0.25).powi(2) + ci * ci;
+    q * (q + (cr - 0.25)) <= 0.25 * ci * ci || (cr + 1.0).powi(2) + ci * ci <= 1.0 / 16.0
+}
+
+// the per channel iteration limits
+pub(crate) fn channel_limits(cfg: &FractalCfg) -> Vec<u32> {
+    if cfg.channel_iterations.is_empty() {
+        vec![cfg.max_iterations]
+    } else {
+        cfg.channel_iterations.clone()
+    }
+}
+
+// everything that escapes has left this radius, and nothing outside it
+// ever comes back. that's 2 for the mandelbrot set, but julia sets with
+// |c| > 2 reach out to |c|
+fn escape_radius(cfg: &FractalCfg) -> f64 {
+    if cfg.julia { cfg.cr.hypot(cfg.ci).max(2.0) } else { 2.0 }
+}
+
+pub fn buddhabrot(cfg: &FractalCfg) -> Density {
+    let grid = PixelGrid::from_cfg(cfg);
+    let radius = escape_radius(cfg);
+    let n_pixels = grid.len();
+
+    let limits = channel_limits(cfg);
+    let max_iterations = *limits.iter().max().unwrap();
+    let n_samples = (cfg.samples as usize) * n_pixels;
+    let n_chunks = (n_samples + CHUNK_SAMPLES - 1) / CHUNK_SAMPLES;
+
+    let counts = (0..n_chunks).into_par_iter()
+        .fold(|| (vec![0u32; n_pixels * limits.len()], vec![]), |(mut counts, mut orbit), chunk| {
+            let mut rng = chunk_rng(cfg.seed, chunk);
+            let n = CHUNK_SAMPLES.min(n_samples - chunk * CHUNK_SAMPLES);
+            for _ in 0..n {
+                // everything that can escape, as a square because that's
+                // easier to sample
+                let pr = rng.gen_range(-radius, radius);
+                let pi = rng.gen_range(-radius, radius);
+                let (cr, ci) = if cfg.julia { (cfg.cr, cfg.ci) } else { (pr, pi) };
+                if !cfg.julia && in_main_bulbs(cr, ci) {
+                    continue;
+                }
+                let (mut zr, mut zi) = if cfg.julia { (pr, pi) } else { (0.0, 0.0) };
+
+                orbit.clear();
+                let mut escaped = false;
+                for _ in 0..max_iterations {
+                    let zr1 = zr * zr - zi * zi + cr;
+                    zi = 2.0 * zr * zi + ci;
+                    zr = zr1;
+                    if zr * zr + zi * zi > radius * radius {
+                        escaped = true;
+                        break;
+                    }
+                    orbit.push(grid.pixel(zr, zi));
+                }
+                if !escaped {
+                    continue;
+                }
+                for (k, &limit) in limits.iter().enumerate() {
+                    if orbit.len() < limit as usize {
+                        let counts = &mut counts[k * n_pixels..(k + 1) * n_pixels];
+                        for &p in orbit.iter() {
+                            if let Some(p) = p {
+                                counts[p] += 1;
+                            }
+                        }
+                    }
+                }
+            }
+            (counts, orbit)
+        })
+        .map(|(counts, _)| counts)
+        // adding integers, so the order they get added in doesn't matter
+        .reduce(|| vec![0u32; n_pixels * limits.len()], |mut a, b| {
+            for (x, y) in a.iter_mut().zip(b.iter()) {
+                *x += *y;
+            }
+            a
+        });
+
+    Density {
+        channels: counts.chunks(n_pixels)
+            .map(|c| c.iter().map(|&x| x as f32).collect())
+            .collect(),
+    }
+}
+
+// counts to [0,1], brightest pixel at 1. the square root brings out the
+// faint orbits, mul brightens everything and offset lifts the background
+pub fn normalize_density(xs: &[f32], mul: f32, offset: f32) -> Vec<f32> {
+    let max = xs.iter().cloned().fold(0f32, f32::max).max(1f32);
+    xs.iter()
+        .map(|&x| ((x / max).sqrt() * mul + offset).max(0f32).min(1f32 - std::f32::EPSILON))
+        .collect()
+}
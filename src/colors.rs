@@ -10,6 +10,7 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::f32::consts::PI;
+use {Interior, Exterior};
 
 pub fn color_map_from_str(s: &str) -> Box<ColorMap> {
     match s {
@@ -46,6 +47,125 @@ pub trait ColorMap {
 
 }
 
+// for signed fields from normalize_signed, each half of which gets its own
+// colormap. NaN is black
+pub struct SignedColorMap {
+    pub negative: Box<ColorMap>,
+    pub positive: Box<ColorMap>,
+}
+impl SignedColorMap {
+    pub fn colorize_buffer(&self, buf: Vec<f32>) -> Vec<u8> {
+        let mut outbuf = vec![0u8; buf.len() * 3];
+        for i in 0..buf.len() {
+            let (r,g,b) = if buf[i].is_nan() {
+                (0,0,0)
+            } else if buf[i].is_sign_negative() {
+                self.negative.colorize(-buf[i])
+            } else {
+                self.positive.colorize(buf[i])
+            };
+            outbuf[3*i + 0] = r;
+            outbuf[3*i + 1] = g;
+            outbuf[3*i + 2] = b;
+        }
+        outbuf
+    }
+}
+
+// one color per root of a newton fractal, taken at evenly spaced points of a
+// colormap and darkened by the (normalized) iteration count
+pub struct RootColors {
+    pub colors: Vec<(u8,u8,u8)>,
+}
+impl RootColors {
+    pub fn new(cmap: &ColorMap, n_roots: usize) -> RootColors {
+        RootColors {
+            colors: (0..n_roots)
+                .map(|i| cmap.colorize((i as f32 + 0.5) / (n_roots as f32)))
+                .collect(),
+        }
+    }
+
+    // roots are indices into colors, or -1 for black
+    pub fn colorize_buffer(&self, roots: &[i32], shade: Vec<f32>) -> Vec<u8> {
+        let mut outbuf = vec![0u8; roots.len() * 3];
+        for i in 0..roots.len() {
+            if roots[i] < 0 || shade[i] < 0f32 {
+                continue;
+            }
+            let (r,g,b) = self.colors[roots[i] as usize % self.colors.len()];
+            // keep some color even on the slowest pixels
+            let x = 1.0 - 0.75 * shade[i];
+            outbuf[3*i + 0] = (r as f32 * x) as u8;
+            outbuf[3*i + 1] = (g as f32 * x) as u8;
+            outbuf[3*i + 2] = (b as f32 * x) as u8;
+        }
+        outbuf
+    }
+}
+
+// colors the inside of the set, which colorize_buffer leaves black, by the
+// cycle each point settles into
+pub struct InteriorColors {
+    pub interior: Interior,
+    pub cmap: Box<ColorMap>,
+}
+impl InteriorColors {
+    // paints over the pixels of rgb that have a period (see Render::period).
+    // the multiplier is needed for Interior::Multiplier, and shades
+    // Interior::Period when it's there
+    pub fn paint(&self, rgb: &mut [u8], period: &[u32], multiplier: Option<&[(f32, f32)]>) {
+        for i in 0..period.len() {
+            if period[i] == 0 {
+                continue;
+            }
+            let modulus = multiplier.map_or(0f32, |m| m[i].0.hypot(m[i].1));
+            let x = match self.interior {
+                // the golden ratio keeps neighbouring periods far apart on
+                // the colormap
+                Interior::Period => ((period[i] - 1) as f32 * 0.618034) % 1f32,
+                Interior::Multiplier => match multiplier {
+                    Some(m) => (m[i].1.atan2(m[i].0) / (2f32 * PI) + 0.5) % 1f32,
+                    None => continue,
+                },
+                Interior::Black => continue,
+            };
+            let (r,g,b) = self.cmap.colorize(x);
+            // |multiplier| goes from 0 at the center of a component to 1 at
+            // its edge
+            let x = 1.0 - 0.75 * modulus.min(1f32);
+            rgb[3*i + 0] = (r as f32 * x) as u8;
+            rgb[3*i + 1] = (g as f32 * x) as u8;
+            rgb[3*i + 2] = (b as f32 * x) as u8;
+        }
+    }
+}
+
+// darkens the outside of the set by the angle z escaped at
+pub struct ExteriorShading {
+    pub exterior: Exterior,
+}
+impl ExteriorShading {
+    // shades the pixels of rgb that have an angle (see Render::angle)
+    pub fn paint(&self, rgb: &mut [u8], angle: &[f32]) {
+        for i in 0..angle.len() {
+            if angle[i].is_nan() {
+                continue;
+            }
+            let x = match self.exterior {
+                Exterior::Binary => if angle[i] < 0f32 { 0.4 } else { 1.0 },
+                // the lines are thin, most of the way from one to the next is
+                // light
+                Exterior::FieldLines => 0.25 + 0.75 * (angle[i].abs() / PI).sqrt(),
+                Exterior::Plain => continue,
+            };
+            for c in rgb[3*i..3*i + 3].iter_mut() {
+                *c = (*c as f32 * x) as u8;
+            }
+        }
+    }
+}
+
 pub struct ColorMapHSV {}
 impl ColorMap for ColorMapHSV {
     fn colorize(&self, x: f32) -> (u8,u8,u8) {
@@ -0,0 +1,98 @@
+// fixed.rs
+use num::{BigInt, ToPrimitive, Float};
+
+// arbitrary precision fixed point number, value = m / 2^bits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fixed {
+    m: BigInt,
+    bits: usize,
+}
+
+impl Fixed {
+    pub fn from_f64(x: f64, bits: usize) -> Fixed {
+        let (mantissa, exp, sign) = x.integer_decode();
+        let m = BigInt::from(mantissa);
+        let shift = bits as i64 + exp as i64;
+        let m = if shift >= 0 {
+            m << (shift as usize)
+        } else {
+            m >> ((-shift) as usize)
+        };
+        Fixed { m: if sign < 0 { -m } else { m }, bits: bits }
+    }
+
+    // parses decimal strings like "-0.743643887037158704752191506114774"
+    // or "1.5e-3", rounding to the nearest multiple of 2^-bits
+    pub fn parse(s: &str, bits: usize) -> Result<Fixed, String> {
+        let err = || format!("failed to parse number {:?}", s);
+        let s = s.trim();
+        let (s, exp10) = match s.find(|c| c == 'e' || c == 'E') {
+            Some(i) => (&s[..i], s[i+1..].parse::<i64>().map_err(|_| err())?),
+            None => (s, 0),
+        };
+        let (negative, s) = if s.starts_with('-') {
+            (true, &s[1..])
+        } else if s.starts_with('+') {
+            (false, &s[1..])
+        } else {
+            (false, s)
+        };
+        let (int_part, frac_part) = match s.find('.') {
+            Some(i) => (&s[..i], &s[i+1..]),
+            None => (s, ""),
+        };
+        if int_part.is_empty() && frac_part.is_empty() {
+            return Err(err());
+        }
+        let digits = format!("{}{}", int_part, frac_part);
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(err());
+        }
+        // 10^exp10 gets worked out in full, so an exponent past anything the
+        // digits and bits can hold is an error instead of a huge number
+        let max_exp10 = (bits as f64 * 2f64.log10()) as i64 + digits.len() as i64;
+        if exp10.abs() > max_exp10 {
+            return Err(err());
+        }
+        let digits = BigInt::parse_bytes(digits.as_bytes(), 10).ok_or_else(err)?;
+        // value = digits * 10^exp10
+        let exp10 = exp10 - frac_part.len() as i64;
+        let ten = BigInt::from(10);
+        let m = if exp10 >= 0 {
+            (digits * num::pow(ten, exp10 as usize)) << bits
+        } else {
+            let den = num::pow(ten, (-exp10) as usize);
+            ((digits << (bits + 1)) / den + 1) >> 1
+        };
+        Ok(Fixed { m: if negative { -m } else { m }, bits: bits })
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // keep the top 64 bits of the mantissa, so tiny values survive too
+        let shift = (self.m.bits() as i64 - 64).max(0);
+        let top = (&self.m >> (shift as usize)).to_f64().unwrap();
+        let exp = shift - self.bits as i64;
+        // split the scale in two so 2^exp doesn't overflow on the way
+        let half = (exp / 2) as i32;
+        top * 2f64.powi(half) * 2f64.powi(exp as i32 - half)
+    }
+
+    pub fn add(&self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed { m: &self.m + &other.m, bits: self.bits }
+    }
+
+    pub fn sub(&self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed { m: &self.m - &other.m, bits: self.bits }
+    }
+
+    pub fn mul(&self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed { m: (&self.m * &other.m) >> self.bits, bits: self.bits }
+    }
+
+    pub fn mul2(&self) -> Fixed {
+        Fixed { m: &self.m << 1, bits: self.bits }
+    }
+}
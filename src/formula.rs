@@ -0,0 +1,330 @@
+// formula.rs
+use std;
+use simd::Vector;
+use newton::parse_complex;
+use num::{Float, Zero, cast};
+
+// highest multibrot and multicorn power. the sets get closer and closer to
+// the unit disk as the power goes up, and by here there's nothing else left
+// to see, while integer powers cost a multiplication each
+pub const MAX_POWER: f64 = 64.0;
+
+// the map z -> f(z, c) that gets iterated. whether c or z_0 comes from the
+// pixel is decided separately by FractalCfg::julia
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Formula {
+    // z^2 + c
+    Mandelbrot,
+    // z^d + c, for any real d > 1. integer powers are a lot faster
+    Multibrot(f64),
+    // conj(z)^d + c, the tricorn (d = 2) and its generalisations
+    Multicorn(f64),
+    // z^2 + c with some of the parts of z or z^2 folded by abs, which
+    // turns the bulbs into all kinds of ships and spikes. with z = x + iy:
+    //   x' = x^2 - y^2 + cr,    y' = 2|xy| + ci
+    BurningShip,
+    //   x' = |x^2 - y^2| + cr,  y' = 2xy + ci
+    Celtic,
+    //   x' = |x^2 - y^2| + cr,  y' = 2|xy| + ci
+    Buffalo,
+    //   x' = x^2 - y^2 + cr,    y' = -2|x|y + ci
+    Perpendicular,
+    // z^2 + c + p * z_(n-1), where z_(n-1) is the z before this one.
+    // the usual julia set is c = 0.5667, p = -0.5
+    Phoenix(f64, f64),
+    // newton's method on FractalCfg::polynomial, see newton.rs
+    Newton,
+    // z - relaxation * p(z)/p'(z) + c, on FractalCfg::polynomial
+    Nova(f64),
+    // lyapunov exponent of the logistic map, with the rate switching between
+    // the real (A) and imaginary (B) part of the point as FractalCfg::sequence
+    // says. not really a formula of z, see lyapunov.rs
+    Lyapunov,
+    // density of escaping z^2 + c orbits, see buddhabrot.rs
+    Buddhabrot,
+    // julia sets of z^2 + c plotted by inverse iteration, see inverse.rs.
+    // Miim stops following preimages into pixels hit more than this often
+    Iim,
+    Miim(u32),
+}
+
+impl Formula {
+    // the power z grows with once it's large, which smooth_iter needs
+    pub fn degree(&self) -> f64 {
+        match *self {
+            Formula::Mandelbrot => 2.0,
+            Formula::Multibrot(d) => d,
+            Formula::Multicorn(d) => d,
+            _ => 2.0,
+        }
+    }
+
+    // rendered by newton.rs instead of the escape time kernel
+    pub fn uses_newton(&self) -> bool {
+        match *self {
+            Formula::Newton | Formula::Nova(_) => true,
+            _ => false,
+        }
+    }
+
+    // false for the renderers that have their own module, which produce
+    // something other than smooth escape times
+    pub fn is_escape_time(&self) -> bool {
+        match *self {
+            Formula::Newton | Formula::Nova(_) | Formula::Lyapunov | Formula::Buddhabrot
+                | Formula::Iim | Formula::Miim(_) => false,
+            _ => true,
+        }
+    }
+
+    // whether the image is a count of points landing in each pixel
+    // (buddhabrot::Density) rather than something worked out per pixel
+    pub fn is_density(&self) -> bool {
+        match *self {
+            Formula::Buddhabrot | Formula::Iim | Formula::Miim(_) => true,
+            _ => false,
+        }
+    }
+
+    // whether the image is a signed field (see colors::SignedColorMap)
+    // rather than escape times with -1 for the inside
+    pub fn is_signed(&self) -> bool {
+        *self == Formula::Lyapunov
+    }
+
+    // z^2 + c, which is all the perturbation renderer and the distance
+    // estimate know
+    pub fn is_quadratic(&self) -> bool {
+        match *self {
+            Formula::Mandelbrot => true,
+            Formula::Multibrot(d) => d == 2.0,
+            _ => false,
+        }
+    }
+
+    // whether the next z depends on more than z and c. if it doesn't, an
+    // orbit that comes back to where it was is periodic
+    pub fn has_orbit_state(&self) -> bool {
+        match *self {
+            Formula::Phoenix(..) => true,
+            _ => false,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Formula::Multibrot(d) if !(d > 1.0 && d <= MAX_POWER) =>
+                Err(format!("multibrot power must be > 1 and at most {}, not {}", MAX_POWER, d)),
+            Formula::Multicorn(d) if !(d > 1.0 && d <= MAX_POWER) =>
+                Err(format!("multicorn power must be > 1 and at most {}, not {}", MAX_POWER, d)),
+            Formula::Miim(0) => Err("miim needs at least 1 hit per pixel".to_owned()),
+            _ => Ok(()),
+        }
+    }
+}
+
+// mandelbrot, multibrot:3, multibrot:2.5, tricorn, multicorn:3, burningship,
+// celtic, buffalo, perpendicular, phoenix, phoenix:-0.5+0.1i, newton, nova,
+// nova:0.5, lyapunov, buddhabrot, iim, miim, miim:50
+impl std::str::FromStr for Formula {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Formula, String> {
+        let mut parts = s.splitn(2, ':');
+        let name = parts.next().unwrap();
+        let arg = parts.next();
+        let power = || arg
+            .ok_or_else(|| format!("formula {} needs a power, like {}:3", name, name))
+            .and_then(|x| x.parse::<f64>().map_err(|_| format!("bad power {}", x)));
+        match name {
+            "mandelbrot" => Ok(Formula::Mandelbrot),
+            "multibrot"  => Ok(Formula::Multibrot(power()?)),
+            "tricorn"    if arg.is_none() => Ok(Formula::Multicorn(2.0)),
+            "multicorn"  => Ok(Formula::Multicorn(power()?)),
+            "burningship"   if arg.is_none() => Ok(Formula::BurningShip),
+            "celtic"        if arg.is_none() => Ok(Formula::Celtic),
+            "buffalo"       if arg.is_none() => Ok(Formula::Buffalo),
+            "perpendicular" if arg.is_none() => Ok(Formula::Perpendicular),
+            "phoenix" => {
+                let (pr, pi) = match arg {
+                    Some(x) => parse_complex(x)?,
+                    None => (-0.5, 0.0),
+                };
+                Ok(Formula::Phoenix(pr, pi))
+            },
+            "newton"        if arg.is_none() => Ok(Formula::Newton),
+            "lyapunov"      if arg.is_none() => Ok(Formula::Lyapunov),
+            "buddhabrot"    if arg.is_none() => Ok(Formula::Buddhabrot),
+            "iim"           if arg.is_none() => Ok(Formula::Iim),
+            "miim" => {
+                let hits = match arg {
+                    Some(x) => x.parse::<u32>().map_err(|_| format!("bad hit count {}", x))?,
+                    None => 10,
+                };
+                Ok(Formula::Miim(hits))
+            },
+            "nova" => {
+                let relaxation = match arg {
+                    Some(x) => x.parse::<f64>().map_err(|_| format!("bad relaxation {}", x))?,
+                    None => 1.0,
+                };
+                Ok(Formula::Nova(relaxation))
+            },
+            _ => Err(format!("unknown formula {}", s)),
+        }
+    }
+}
+
+// one step of a formula, on vectors of points
+pub(crate) trait Iteration<V: Vector> {
+    // whatever else the formula carries along the orbit besides z, like
+    // the previous z for the phoenix
+    type State: Copy;
+    fn init(&self, zr: V, zi: V) -> Self::State;
+    fn step(&self, zr: V, zi: V, cr: V, ci: V, state: &mut Self::State) -> (V, V);
+}
+
+pub(crate) struct Quadratic;
+
+impl<V: Vector> Iteration<V> for Quadratic {
+    type State = ();
+    #[inline(always)]
+    fn init(&self, _: V, _: V) {}
+    #[inline(always)]
+    fn step(&self, zr: V, zi: V, cr: V, ci: V, _: &mut ()) -> (V, V) {
+        let zr2 = zr * zr;
+        let zi2 = zi * zi;
+        /* zr1 = zr0 * zr0 - zi0 * zi0 + cr */
+        /* zi1 = zr0 * zi0 + zr0 * zi0 + ci */
+        (zr2 - zi2 + cr, (zr + zr).mul_add(zi, ci))
+    }
+}
+
+// runs F on conj(z) instead of z
+pub(crate) struct Conjugate<F>(pub F);
+
+impl<V: Vector, F: Iteration<V>> Iteration<V> for Conjugate<F> {
+    type State = F::State;
+    #[inline(always)]
+    fn init(&self, zr: V, zi: V) -> F::State {
+        self.0.init(zr, zi)
+    }
+    #[inline(always)]
+    fn step(&self, zr: V, zi: V, cr: V, ci: V, state: &mut F::State) -> (V, V) {
+        let zero = V::splat(V::Elem::zero());
+        self.0.step(zr, zero - zi, cr, ci, state)
+    }
+}
+
+pub(crate) struct BurningShip;
+
+impl<V: Vector> Iteration<V> for BurningShip {
+    type State = ();
+    #[inline(always)]
+    fn init(&self, _: V, _: V) {}
+    #[inline(always)]
+    fn step(&self, zr: V, zi: V, cr: V, ci: V, _: &mut ()) -> (V, V) {
+        let (ar, ai) = (zr.abs(), zi.abs());
+        (zr * zr - zi * zi + cr, (ar + ar).mul_add(ai, ci))
+    }
+}
+
+pub(crate) struct Celtic;
+
+impl<V: Vector> Iteration<V> for Celtic {
+    type State = ();
+    #[inline(always)]
+    fn init(&self, _: V, _: V) {}
+    #[inline(always)]
+    fn step(&self, zr: V, zi: V, cr: V, ci: V, _: &mut ()) -> (V, V) {
+        ((zr * zr - zi * zi).abs() + cr, (zr + zr).mul_add(zi, ci))
+    }
+}
+
+pub(crate) struct Buffalo;
+
+impl<V: Vector> Iteration<V> for Buffalo {
+    type State = ();
+    #[inline(always)]
+    fn init(&self, _: V, _: V) {}
+    #[inline(always)]
+    fn step(&self, zr: V, zi: V, cr: V, ci: V, _: &mut ()) -> (V, V) {
+        ((zr * zr - zi * zi).abs() + cr, ((zr + zr) * zi).abs() + ci)
+    }
+}
+
+pub(crate) struct Perpendicular;
+
+impl<V: Vector> Iteration<V> for Perpendicular {
+    type State = ();
+    #[inline(always)]
+    fn init(&self, _: V, _: V) {}
+    #[inline(always)]
+    fn step(&self, zr: V, zi: V, cr: V, ci: V, _: &mut ()) -> (V, V) {
+        let ar = zr.abs();
+        (zr * zr - zi * zi + cr, ci - (ar + ar) * zi)
+    }
+}
+
+// z^2 + c + p * z_(n-1)
+pub(crate) struct Phoenix(pub f64, pub f64);
+
+impl<V: Vector> Iteration<V> for Phoenix {
+    // the previous z
+    type State = (V, V);
+    #[inline(always)]
+    fn init(&self, _: V, _: V) -> (V, V) {
+        let zero = V::splat(V::Elem::zero());
+        (zero, zero)
+    }
+    #[inline(always)]
+    fn step(&self, zr: V, zi: V, cr: V, ci: V, state: &mut (V, V)) -> (V, V) {
+        let pr = V::splat(cast(self.0).unwrap());
+        let pi = V::splat(cast(self.1).unwrap());
+        let (yr, yi) = *state;
+        *state = (zr, zi);
+        (
+            zr * zr - zi * zi + cr + pr * yr - pi * yi,
+            (zr + zr).mul_add(zi, ci) + pr.mul_add(yi, pi * yr),
+        )
+    }
+}
+
+// z^d by repeated multiplication
+pub(crate) struct IntPower(pub u32);
+
+impl<V: Vector> Iteration<V> for IntPower {
+    type State = ();
+    #[inline(always)]
+    fn init(&self, _: V, _: V) {}
+    #[inline(always)]
+    fn step(&self, zr: V, zi: V, cr: V, ci: V, _: &mut ()) -> (V, V) {
+        let (mut pr, mut pi) = (zr, zi);
+        for _ in 1..self.0 {
+            let r = pr * zr - pi * zi;
+            pi = pr.mul_add(zi, pi * zr);
+            pr = r;
+        }
+        (pr + cr, pi + ci)
+    }
+}
+
+// z^d in polar form, lane by lane since there are no vector pow/sin/cos
+pub(crate) struct RealPower(pub f64);
+
+impl<V: Vector> Iteration<V> for RealPower {
+    type State = ();
+    #[inline(always)]
+    fn init(&self, _: V, _: V) {}
+    #[inline(always)]
+    fn step(&self, zr: V, zi: V, cr: V, ci: V, _: &mut ()) -> (V, V) {
+        let d: V::Elem = cast(self.0).unwrap();
+        let half: V::Elem = cast(0.5).unwrap();
+        let (pr, pi) = V::map2(zr, zi, |zr, zi| {
+            // |z|^d = (|z|^2)^(d/2), saves a sqrt
+            let r = (zr*zr + zi*zi).powf(d * half);
+            let theta = zi.atan2(zr) * d;
+            (r * theta.cos(), r * theta.sin())
+        });
+        (pr + cr, pi + ci)
+    }
+}
@@ -1,125 +1,783 @@
 // fractal.rs
-use FractalCfg;
-use x86intrin::avx::*;
+use {FractalCfg, Precision, Interior, Exterior};
+use backend::Backend;
+use simd::{Vector, Mask, MAX_LANES};
+use perturbation::{perturbation, parse_center, precision_bits};
+use newton::{newton, nova};
+use lyapunov::lyapunov;
+use buddhabrot::buddhabrot;
+use inverse::inverse_iteration;
+use trap::VectorTrap;
+use average::{Average, VectorAverage, smooth_average};
+use render::{Channels, Render, RenderRows};
+use formula::{Formula, Iteration, Quadratic, IntPower, RealPower};
+use formula::{Conjugate, BurningShip, Celtic, Buffalo, Perpendicular, Phoenix};
+use num::{Float, Zero, One, ToPrimitive, Complex, cast};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::{Arc, Mutex};
 
-fn calc_width(
+// how close, in pixels, an orbit has to come back to count as a cycle
+const PERIOD_TOLERANCE: f64 = 1e-3;
+
+pub(crate) fn calc_width<T: Float>(
         x:usize, y: usize,
-        zoom: f32
-    ) -> (f32,f32) {
-    let x = x as f32;
-    let y = y as f32;
-    let dz = 4f32 / zoom;
+        zoom: T
+    ) -> (T,T) {
+    let x: T = cast(x).unwrap();
+    let y: T = cast(y).unwrap();
+    let dz = cast::<_, T>(4).unwrap() / zoom;
     if x > y {
-        (1f32 * x / y * dz, dz)
+        (x / y * dz, dz)
     } else {
-        (dz, 1f32 * x / y * dz)
+        (dz, x / y * dz)
     }
 }
 
 
-fn smooth_iter(iter: f32, mag: f32) -> f32 {
-    let log_zn = mag.log2()/2f32;
-    let nu = log_zn.log2();
+// iter is the iteration that took z past the bailout, mag2 the |z|^2 it got
+// to, and degree the power z grows with, 2 for z^2 + c. every iteration
+// multiplies log|z| by about degree, so this comes out the same whatever the
+// bailout is, as long as it's big enough next to c
+pub(crate) fn smooth_iter(iter: f32, mag2: f32, degree: f32) -> f32 {
+    let log_zn = mag2.log2()/2f32;
+    let nu = log_zn.log2() / degree.log2();
     iter - nu + 1f32
 }
 
+// exterior distance estimate 2*|z|*ln|z| / |dz| from the squared magnitudes,
+// within a factor of 4 of the distance to the set
+pub(crate) fn distance(mag2: f64, dz2: f64) -> f64 {
+    (mag2 / dz2).sqrt() * mag2.ln()
+}
+
+impl FractalCfg {
+    // center_r/center_i, or the decimal strings when they are set
+    pub fn center(&self) -> Result<(f64, f64), String> {
+        let parse = |s: &String, fallback: f64| if s.is_empty() {
+            Ok(fallback)
+        } else {
+            s.parse().map_err(|_| format!("bad center {:?}", s))
+        };
+        Ok((parse(&self.center_r_str, self.center_r)?, parse(&self.center_i_str, self.center_i)?))
+    }
+
+    // the center for the renderers, which only get cfgs that validate, and
+    // validate has already checked the strings parse
+    pub(crate) fn view_center(&self) -> (f64, f64) {
+        self.center().unwrap_or((self.center_r, self.center_i))
+    }
+
+    // precision that Auto resolves to for this view
+    pub fn resolve_precision(&self) -> Precision {
+        let precision = match self.precision {
+            Precision::Auto => {
+                let (xwidth, ywidth) = calc_width(self.width as usize, self.height as usize, self.zoom);
+                let pixel = xwidth / (self.width as f64);
+                let (center_r, center_i) = self.view_center();
+                let magnitude = (center_r.abs() + xwidth/2.0).max(center_i.abs() + ywidth/2.0);
+                // floats fall apart into blocks once a pixel gets down to a
+                // few ulps, so switch over while we still have some bits left
+                if pixel > magnitude * (std::f32::EPSILON as f64) * 32.0 {
+                    Precision::Single
+                } else if pixel > magnitude * std::f64::EPSILON * 32.0 {
+                    Precision::Double
+                } else {
+                    Precision::Perturbation
+                }
+            },
+            p => p,
+        };
+        // the renderers outside of escape_time always iterate in f64
+        if !self.formula.is_escape_time() {
+            return Precision::Double;
+        }
+        // perturbation only does plain escape times
+        if precision == Precision::Perturbation
+            && (!self.formula.is_quadratic() || self.trap.is_some() || self.distance_estimate
+                || self.average.is_some() || self.interior != Interior::Black
+                || self.exterior != Exterior::Plain) {
+            Precision::Double
+        } else {
+            precision
+        }
+    }
+
+    // checks that the image can be rendered at all
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err(format!("Bad image size! {}x{} has no pixels", self.width, self.height));
+        }
+        if !(self.bailout > 1.0) {
+            return Err(format!("bailout radius must be > 1, not {}", self.bailout));
+        }
+        let (center_r, center_i) = self.center()?;
+        if !center_r.is_finite() || !center_i.is_finite() {
+            return Err(format!("bad center {} {}", center_r, center_i));
+        }
+        if !(self.zoom > 0.0) || !self.zoom.is_finite() {
+            return Err(format!("zoom must be positive, not {}", self.zoom));
+        }
+        // perturbation reads the strings again in fixed point, with as many
+        // bits as the zoom needs
+        parse_center(self, precision_bits(self))?;
+        self.formula.validate()?;
+        if self.trap.is_some() && !self.formula.is_escape_time() {
+            return Err(format!("orbit traps don't work with {:?}", self.formula));
+        }
+        if self.distance_estimate && !self.formula.is_quadratic() {
+            return Err(format!("distance estimation doesn't work with {:?}", self.formula));
+        }
+        if self.distance_estimate && self.trap.is_some() {
+            return Err("pick either an orbit trap or the distance estimate".to_owned());
+        }
+        if let Some(average) = self.average {
+            if !self.formula.is_escape_time() {
+                return Err(format!("averages don't work with {:?}", self.formula));
+            }
+            // the bounds on |z| need the next z to be z^d + c
+            if average == Average::Triangle && self.formula.has_orbit_state() {
+                return Err(format!("the triangle inequality average doesn't work with {:?}", self.formula));
+            }
+            if self.trap.is_some() || self.distance_estimate {
+                return Err("pick one of an orbit trap, the distance estimate or an average".to_owned());
+            }
+        }
+        if self.interior != Interior::Black {
+            // periods come from the periodicity check, which needs the next z
+            // to depend on z alone
+            if !self.formula.is_escape_time() || self.formula.has_orbit_state() {
+                return Err(format!("interior coloring doesn't work with {:?}", self.formula));
+            }
+            if self.trap.is_some() {
+                return Err("pick either an orbit trap or interior coloring".to_owned());
+            }
+            if !self.periodicity_check {
+                return Err("interior coloring needs the periodicity check".to_owned());
+            }
+            if self.interior == Interior::Multiplier && !self.formula.is_quadratic() {
+                return Err(format!("cycle multipliers are only known for z^2 + c, not {:?}", self.formula));
+            }
+        }
+        if self.exterior != Exterior::Plain && !self.formula.is_escape_time() {
+            return Err(format!("exterior coloring doesn't work with {:?}", self.formula));
+        }
+        if let Some(relief) = self.relief {
+            relief.validate()?;
+            // densities aren't fields to light, and signed fields have no
+            // inside to leave out
+            if self.formula.is_density() || self.formula.is_signed() {
+                return Err(format!("relief doesn't work with {:?}", self.formula));
+            }
+        }
+        // the rays are the mandelbrot set's, traced with z^2 + c
+        if !self.rays.is_empty() && (!self.formula.is_quadratic() || self.julia) {
+            return Err(format!("external rays only go with the mandelbrot set, not {:?}{}",
+                               self.formula, if self.julia { " julia sets" } else { "" }));
+        }
+        if self.references == 0 {
+            return Err("perturbation needs at least one reference orbit".to_owned());
+        }
+        if !self.rays.is_empty() && self.ray_depth == 0 {
+            return Err("rays need a depth of at least 1".to_owned());
+        }
+        if self.formula.uses_newton() && self.polynomial.degree() < 2 {
+            return Err(format!("{:?} needs a polynomial of degree 2 or more, not {}", self.formula, self.polynomial.degree()));
+        }
+        // a saved config can have its roots out of step with its coefficients
+        if self.formula.uses_newton() && self.polynomial.roots.len() != self.polynomial.degree() {
+            return Err(format!("polynomial of degree {} needs {} roots, not {}", self.polynomial.degree(),
+                               self.polynomial.degree(), self.polynomial.roots.len()));
+        }
+        if self.formula == Formula::Iim && self.samples == 0 {
+            return Err("inverse iteration needs at least one sample per pixel".to_owned());
+        }
+        match self.formula {
+            Formula::Iim | Formula::Miim(_) if !self.julia => {
+                return Err("inverse iteration only draws julia sets, of cr + ci i".to_owned());
+            },
+            _ => {},
+        }
+        if self.formula == Formula::Buddhabrot {
+            if self.samples == 0 {
+                return Err("buddhabrot needs at least one sample per pixel".to_owned());
+            }
+            if !(self.channel_iterations.is_empty() || self.channel_iterations.len() == 3) {
+                return Err(format!("nebulabrot needs 3 iteration limits, one per color, not {}", self.channel_iterations.len()));
+            }
+        }
+        if self.formula == Formula::Lyapunov {
+            if self.sequence.is_empty() || !self.sequence.chars().all(|c| c == 'A' || c == 'B') {
+                return Err(format!("lyapunov sequence must be made of A and B, not {:?}", self.sequence));
+            }
+        }
+        let scale = if self.downsample { 2 } else { 1 };
+        (self.width as usize).checked_mul(scale)
+            .and_then(|w| w.checked_mul(self.height as usize))
+            .and_then(|n| n.checked_mul(scale))
+            // the rgb buffer is the largest one we allocate
+            .and_then(|n| n.checked_mul(3 * std::mem::size_of::<f32>()))
+            .map(|_| ())
+            .ok_or_else(|| format!("Bad image size! {}x{} is too large", self.width, self.height))
+    }
+
+    // the channels the coloring needs besides the value
+    pub fn channels(&self) -> Channels {
+        let interior = match self.interior {
+            Interior::Black => Channels::default(),
+            _ => Channels { period: true, multiplier: self.formula.is_quadratic(), .. Channels::default() },
+        };
+        interior.union(Channels { angle: self.exterior != Exterior::Plain, .. Channels::default() })
+    }
+
+    // whether the SIMD kernel should iterate in f64
+    pub fn use_double(&self) -> bool {
+        self.resolve_precision() == Precision::Double
+    }
+}
+
+// what the renderer did to produce an image, for printing
+#[derive(Debug, Clone, Default)]
+pub struct RenderStats {
+    // perturbation: reference orbits computed
+    pub references: usize,
+    // perturbation: pixels that had to be re-rendered with a new reference
+    pub glitched: usize,
+    // perturbation: iterations skipped by the series approximation
+    pub series_skipped: u32,
+}
+
+// renders with the fastest backend this cpu supports
 pub fn mandelbrot(cfg: &FractalCfg) -> Vec<f32> {
-    let width          = cfg.width    as usize;
-    let height         = cfg.height   as usize;
-    let center_r       = cfg.center_r as f32;
-    let center_i       = cfg.center_i as f32;
-    let zoom           = cfg.zoom     as f32;
-    let cr0            = cfg.cr       as f32;
-    let ci0            = cfg.ci       as f32;
+    mandelbrot_with_backend(cfg, Backend::detect())
+}
+
+pub fn mandelbrot_with_backend(cfg: &FractalCfg, backend: Backend) -> Vec<f32> {
+    mandelbrot_with_stats(cfg, backend).0
+}
+
+pub fn mandelbrot_with_stats(cfg: &FractalCfg, backend: Backend) -> (Vec<f32>, RenderStats) {
+    let (render, stats) = render_with_stats(cfg, backend, Channels::default());
+    (render.value, stats)
+}
+
+// like mandelbrot, with the channels asked for filled in as well
+pub fn render(cfg: &FractalCfg, channels: Channels) -> Render {
+    render_with_backend(cfg, Backend::detect(), channels)
+}
+
+pub fn render_with_backend(cfg: &FractalCfg, backend: Backend, channels: Channels) -> Render {
+    render_with_stats(cfg, backend, channels).0
+}
+
+pub fn render_with_stats(cfg: &FractalCfg, backend: Backend, channels: Channels) -> (Render, RenderStats) {
+    if let Err(e) = cfg.validate() {
+        panic!("{}", e);
+    }
+    let width  = cfg.width  as usize;
+    let height = cfg.height as usize;
+
+    // the other renderers only have the value
+    let value_only = |value| (Render::from_value(value), RenderStats::default());
+    match cfg.formula {
+        Formula::Newton => return value_only(newton(cfg).iterations),
+        Formula::Nova(_) => return value_only(nova(cfg)),
+        Formula::Lyapunov => return value_only(lyapunov(cfg)),
+        Formula::Buddhabrot => return value_only(buddhabrot(cfg).total()),
+        Formula::Iim | Formula::Miim(_) => return value_only(inverse_iteration(cfg).total()),
+        _ => {},
+    }
+    if cfg.resolve_precision() == Precision::Perturbation {
+        let (value, stats) = with_threads(cfg, || perturbation(cfg));
+        return (Render::from_value(value), stats);
+    }
+
+    let mut render = Render::new(width * height, channels);
+    with_threads(cfg, || {
+        render.rows(width)
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(row, mut out)| backend.render(cfg, row, &mut out));
+    });
+    (render, RenderStats::default())
+}
+
+// runs f on a pool with cfg.threads threads, or on the global pool if that's 0
+pub(crate) fn with_threads<F, R>(cfg: &FractalCfg, f: F) -> R
+    where F: FnOnce() -> R + Send, R: Send {
+    if cfg.threads == 0 {
+        f()
+    } else {
+        thread_pool(cfg.threads).install(f)
+    }
+}
+
+// the pool for a thread count, built the first time it's asked for and kept
+// for every render after that
+fn thread_pool(threads: usize) -> Arc<ThreadPool> {
+    static POOLS: Mutex<Vec<(usize, Arc<ThreadPool>)>> = Mutex::new(Vec::new());
+    let mut pools = POOLS.lock().unwrap();
+    if let Some(&(_, ref pool)) = pools.iter().find(|&&(n, _)| n == threads) {
+        return pool.clone();
+    }
+    let pool = Arc::new(ThreadPoolBuilder::new().num_threads(threads).build()
+        .expect("failed to create thread pool"));
+    pools.push((threads, pool.clone()));
+    pool
+}
+
+// runs f on the point of every pixel, in parallel. for the renderers that
+// don't go through escape_time
+pub(crate) fn map_pixels<T, F>(cfg: &FractalCfg, f: F) -> Vec<T>
+    where T: Copy + Send + Default, F: Fn(Complex<f64>) -> T + Sync {
+    let width  = cfg.width  as usize;
+    let height = cfg.height as usize;
+    let (center_r, center_i) = cfg.view_center();
+    let (xwidth, ywidth) = calc_width(width, height, cfg.zoom);
+    let xscale = xwidth / (width as f64);
+    let yscale = ywidth / (height as f64);
+
+    let mut pixels = vec![T::default(); width * height];
+    with_threads(cfg, || {
+        pixels.par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(row, pixels)| {
+                // rows are stored top to bottom, y goes up
+                let y = height - row - 1;
+                for x in 0..width {
+                    pixels[x] = f(Complex::new(
+                        center_r - xwidth / 2.0 + (x as f64) * xscale,
+                        center_i - ywidth / 2.0 + (y as f64) * yscale,
+                    ));
+                }
+            });
+    });
+    pixels
+}
+
+// where points land in an image, for the renderers that plot points instead
+// of coloring each pixel by its own
+pub(crate) struct PixelGrid {
+    width: usize,
+    height: usize,
+    xmin: f64,
+    ymin: f64,
+    xscale: f64,
+    yscale: f64,
+}
+
+impl PixelGrid {
+    pub fn new(width: usize, height: usize, (center_r, center_i): (f64, f64), zoom: f64) -> PixelGrid {
+        let (xwidth, ywidth) = calc_width(width, height, zoom);
+        PixelGrid {
+            width: width,
+            height: height,
+            xmin: center_r - xwidth / 2.0,
+            ymin: center_i - ywidth / 2.0,
+            xscale: xwidth / (width as f64),
+            yscale: ywidth / (height as f64),
+        }
+    }
+
+    // the view of cfg
+    pub fn from_cfg(cfg: &FractalCfg) -> PixelGrid {
+        PixelGrid::new(cfg.width as usize, cfg.height as usize, cfg.view_center(), cfg.zoom)
+    }
+
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    // width of a pixel in the plane
+    pub fn pixel_size(&self) -> f64 {
+        self.xscale
+    }
+
+    // where a point is in the image, as (x, row) with rows top to bottom
+    // like the buffers. pixel centers are whole numbers, and points outside
+    // the image are outside 0..width and 0..height
+    pub fn position(&self, zr: f64, zi: f64) -> (f64, f64) {
+        let x = (zr - self.xmin) / self.xscale;
+        let y = (zi - self.ymin) / self.yscale;
+        (x, (self.height as f64) - y - 1.0)
+    }
+
+    // pixel index of a point, if it lands in the image. rounds the same way
+    // the other renderers place pixels
+    #[inline]
+    pub fn pixel(&self, zr: f64, zi: f64) -> Option<usize> {
+        let x = ((zr - self.xmin) / self.xscale).round();
+        let y = ((zi - self.ymin) / self.yscale).round();
+        if x >= 0.0 && y >= 0.0 && (x as usize) < self.width && (y as usize) < self.height {
+            Some((self.height - (y as usize) - 1) * self.width + (x as usize))
+        } else {
+            None
+        }
+    }
+}
+
+// the escape time loop, written once for every Vector type.
+// inlined so that it is compiled with the target features of the caller
+#[inline(always)]
+pub(crate) fn escape_time<V: Vector>(cfg: &FractalCfg, first_row: usize, out: &mut RenderRows) {
+    match cfg.formula {
+        Formula::Mandelbrot => escape_time_with::<V, _>(cfg, Quadratic, first_row, out),
+        Formula::Multibrot(d) if d == 2.0 => escape_time_with::<V, _>(cfg, Quadratic, first_row, out),
+        Formula::Multibrot(d) if d.fract() == 0.0 => escape_time_with::<V, _>(cfg, IntPower(d as u32), first_row, out),
+        Formula::Multibrot(d) => escape_time_with::<V, _>(cfg, RealPower(d), first_row, out),
+        Formula::Multicorn(d) if d == 2.0 => escape_time_with::<V, _>(cfg, Conjugate(Quadratic), first_row, out),
+        Formula::Multicorn(d) if d.fract() == 0.0 => escape_time_with::<V, _>(cfg, Conjugate(IntPower(d as u32)), first_row, out),
+        Formula::Multicorn(d) => escape_time_with::<V, _>(cfg, Conjugate(RealPower(d)), first_row, out),
+        Formula::BurningShip   => escape_time_with::<V, _>(cfg, BurningShip, first_row, out),
+        Formula::Celtic        => escape_time_with::<V, _>(cfg, Celtic, first_row, out),
+        Formula::Buffalo       => escape_time_with::<V, _>(cfg, Buffalo, first_row, out),
+        Formula::Perpendicular => escape_time_with::<V, _>(cfg, Perpendicular, first_row, out),
+        Formula::Phoenix(pr, pi) => escape_time_with::<V, _>(cfg, Phoenix(pr, pi), first_row, out),
+        Formula::Newton | Formula::Nova(_) | Formula::Lyapunov | Formula::Buddhabrot
+            | Formula::Iim | Formula::Miim(_) => unreachable!(),
+    }
+}
+
+#[inline(always)]
+fn escape_time_with<V: Vector, F: Iteration<V>>(cfg: &FractalCfg, formula: F, first_row: usize, out: &mut RenderRows) {
+    let width          = cfg.width  as usize;
+    let height         = cfg.height as usize;
+    let (center_r, center_i) = cfg.view_center();
+    let center_r: V::Elem = cast(center_r).unwrap();
+    let center_i: V::Elem = cast(center_i).unwrap();
+    let zoom:     V::Elem = cast(cfg.zoom).unwrap();
+    let cr0:      V::Elem = cast(cfg.cr).unwrap();
+    let ci0:      V::Elem = cast(cfg.ci).unwrap();
     let max_iterations = cfg.max_iterations;
+    let degree = cfg.formula.degree() as f32;
+    let trap = cfg.trap.map(VectorTrap::<V>::new);
+    let distance_estimate = cfg.distance_estimate;
+    let average = cfg.average.map(|a| VectorAverage::<V>::new(a, cfg.formula.degree()));
+    let want_final_z = out.final_z.is_some();
+    let want_angle = out.angle.is_some();
+    let want_derivative = out.derivative.is_some();
+    let want_min_modulus = out.min_modulus.is_some();
+    let want_period = out.period.is_some();
+    // the multiplier is only worked out for z^2 + c
+    let want_multiplier = out.multiplier.is_some() && cfg.formula.is_quadratic();
+    let find_period = want_period || want_multiplier;
+    let track_dz = distance_estimate || want_derivative;
 
-    let mut buf = vec![0f32; width * height];
-    
+    let two: V::Elem = cast(2).unwrap();
     let (xwidth, ywidth) = calc_width(width, height, zoom);
-    let xscale = mm256_set1_ps(xwidth / (width as f32));
-    let yscale = mm256_set1_ps(ywidth / (height as f32));
-    let xmin   = mm256_set1_ps(center_r - xwidth / 2f32);
-    let ymin   = mm256_set1_ps(center_i - ywidth / 2f32);
-
-    let threshold = mm256_set1_ps((max_iterations as f32).powi(2));
-    let one = mm256_set1_ps(1f32);
-
-    if width % 8 != 0 {
-        panic!("Bad image size! width must be a multiple of 8");
-    }
-    
-    for y in 0..height {
-        for x in (0..(width/8)).map(|x| x*8) {
-
-            let mx = mm256_set_ps(
-                (x + 7) as f32,
-                (x + 6) as f32,
-                (x + 5) as f32,
-                (x + 4) as f32,
-                (x + 3) as f32,
-                (x + 2) as f32,
-                (x + 1) as f32,
-                (x + 0) as f32
-            );
-            let my = mm256_set1_ps(y as f32);
+    let xscale = V::splat(xwidth / cast(width).unwrap());
+    let yscale = V::splat(ywidth / cast(height).unwrap());
+    let xmin   = V::splat(center_r - xwidth / two);
+    let ymin   = V::splat(center_i - ywidth / two);
+    // the distance estimate is given in pixels
+    let pixel = calc_width(width, height, cfg.zoom).0 / (width as f64);
+
+    let threshold = V::splat(cast(cfg.bailout.powi(2)).unwrap());
+    let zero = V::splat(V::Elem::zero());
+    let one  = V::splat(V::Elem::one());
+    let quarter = V::splat(cast(0.25).unwrap());
+    let sixteenth = V::splat(cast(0.0625).unwrap());
+    let four = V::splat(cast(4).unwrap());
+    let mk_inside = V::splat(cast(max_iterations).unwrap());
+
+    // both checks give up on orbits that stay in before they're done, but
+    // the distance to a trap and some of the channels need the whole orbit.
+    // cycles are still looked for then, for the period and multiplier
+    // channels, they just don't stop the orbit
+    let whole_orbit = trap.is_some() || out.need_whole_orbit();
+    let bulb_check = cfg.bulb_check && !cfg.julia && cfg.formula.is_quadratic() && !whole_orbit;
+    let periodicity_check = cfg.periodicity_check && !cfg.formula.has_orbit_state()
+        && (!whole_orbit || find_period);
+    // an orbit that comes back this close to a point it was at is taken to
+    // be cycling. well under a pixel, so the only orbits it catches are ones
+    // that would have stayed in for a lot longer anyway
+    let period_tolerance = V::splat(cast((pixel * PERIOD_TOLERANCE).powi(2)).unwrap());
+
+    let mut lanes = [V::Elem::zero(); MAX_LANES];
+    let mut mk_out = [V::Elem::zero(); MAX_LANES];
+    let mut mag2_out = [V::Elem::zero(); MAX_LANES];
+    let mut trap_out = [V::Elem::zero(); MAX_LANES];
+    let mut dz2_out = [V::Elem::zero(); MAX_LANES];
+    let mut period_out = [V::Elem::zero(); MAX_LANES];
+    let mut sum_out = [V::Elem::zero(); MAX_LANES];
+    let mut last_out = [V::Elem::zero(); MAX_LANES];
+    let mut count_out = [V::Elem::zero(); MAX_LANES];
+
+    for row in 0..(out.value.len() / width) {
+        // rows are stored top to bottom, y goes up
+        let y = height - (first_row + row) - 1;
+        // the last vector hangs off the edge of the image when width isn't a
+        // multiple of LANES, those lanes are iterated but never stored
+        for x in (0..((width + V::LANES - 1)/V::LANES)).map(|x| x*V::LANES) {
+            let n_lanes = V::LANES.min(width - x);
+
+            for i in 0..V::LANES {
+                lanes[i] = cast(x + i).unwrap();
+            }
+            let mx = V::load(&lanes);
+            let my = V::splat(cast(y).unwrap());
 
             let (cr,ci,mut zr, mut zi) = if cfg.julia {
-                let cr = mm256_set1_ps(cr0);
-                let ci = mm256_set1_ps(ci0);
-                let zr = mm256_add_ps(mm256_mul_ps(mx, xscale), xmin);
-                let zi = mm256_add_ps(mm256_mul_ps(my, yscale), ymin);
+                let cr = V::splat(cr0);
+                let ci = V::splat(ci0);
+                let zr = mx.mul_add(xscale, xmin);
+                let zi = my.mul_add(yscale, ymin);
                 (cr,ci,zr,zi)
             } else {
-                let cr = mm256_add_ps(mm256_mul_ps(mx, xscale), xmin);
-                let ci = mm256_add_ps(mm256_mul_ps(my, yscale), ymin);
+                let cr = mx.mul_add(xscale, xmin);
+                let ci = my.mul_add(yscale, ymin);
                 let zr = cr;
                 let zi = ci;
                 (cr,ci,zr,zi)
             };
 
-            let mut mk = mm256_set1_ps(1f32);
-            let mut mag2final = mm256_set1_ps(0f32);
+            let mut state = formula.init(zr, zi);
+            let mut mk = one;
+            // lanes known to be inside get a limit of 0, which nothing is
+            // under, so they stop counting and stop holding up the loop
+            let mut limit = threshold;
+            let mut period_found = zero;
+            let mut in_bulbs = zero;
+            if bulb_check {
+                // main cardioid and period 2 bulb
+                let xq = cr - quarter;
+                let ci2 = ci * ci;
+                let q = xq.mul_add(xq, ci2);
+                let cardioid = (q * (q + xq)).lt(quarter * ci2);
+                let xb = cr + one;
+                let bulb = xb.mul_add(xb, ci2).lt(sixteenth);
+                // 1 inside, 0 outside
+                let inside = V::select(cardioid, one, V::select(bulb, one, zero));
+                limit = threshold - threshold * inside;
+                mk = (mk_inside - one).mul_add(inside, one);
+                period_found = V::select(cardioid, one, V::select(bulb, one + one, zero));
+                in_bulbs = inside;
+            }
+            // brent's cycle detection: compare against a saved z, and save
+            // a new one after twice as many iterations each time
+            let (mut saved_r, mut saved_i) = (zr, zi);
+            let mut period = 1;
+            let mut since_saved = 0;
+            let mut mag2final = zero;
+            let (mut zr_final, mut zi_final) = (zr, zi);
+            let mut min_mag2 = zr.mul_add(zr, zi * zi);
+            // closest the orbit got to the trap so far
+            let mut trap_min = match trap {
+                Some(ref trap) => trap.distance(zr, zi),
+                None => zero,
+            };
+            // dz/dc for the mandelbrot set, dz/dz0 for julia sets. both
+            // start at 1, since z0 = c or z0 = the point
+            let (mut dzr, mut dzi) = (one, zero);
+            let (mut dzr_final, mut dzi_final) = (one, zero);
+            // the terms of the average so far, and the last one of them
+            let (mut sum, mut last, mut count) = (zero, zero, zero);
+            let c_mod = cr.mul_add(cr, ci * ci).sqrt();
+            // lanes that were still in before this step
+            let mut live = zero.lt(one);
             for _ in 0..max_iterations {
+                if track_dz {
+                    // dz' = 2*z*dz (+ 1), from the z before this step
+                    let r = zr * dzr - zi * dzi;
+                    let i = zr.mul_add(dzi, zi * dzr);
+                    dzr = if cfg.julia { r + r } else { r + r + one };
+                    dzi = i + i;
+                }
+                let prev_mag2 = zr.mul_add(zr, zi * zi);
                 /* Compute z1 from z0 */
-                let zr2 = mm256_mul_ps(zr, zr);
-                let zi2 = mm256_mul_ps(zi, zi);
-                let zrzi = mm256_mul_ps(zr, zi);
-
-                /* zr1 = zr0 * zr0 - zi0 * zi0 + cr */
-                /* zi1 = zr0 * zi0 + zr0 * zi0 + ci */
-                zr = mm256_add_ps(mm256_sub_ps(zr2, zi2), cr);
-                zi = mm256_add_ps(mm256_add_ps(zrzi, zrzi), ci);
+                let (zr1, zi1) = formula.step(zr, zi, cr, ci, &mut state);
+                zr = zr1;
+                zi = zi1;
 
                 /* Increment k */
-                let zr2 = mm256_mul_ps(zr, zr);
-                let zi2 = mm256_mul_ps(zi, zi);
-                let mag2 = mm256_add_ps(zr2, zi2);
-                let mask = mm256_cmp_ps(mag2, threshold, CMP_LT_OS);
-                mk = mm256_add_ps(mm256_and_ps(mask, one), mk);
-                // save the magnitude at the maximum iteration
-                mag2final = mm256_or_ps(
-                    mm256_and_ps(mask, mag2), 
-                    mm256_andnot_ps(mask, mag2final));
-                // we can't just use the magnitude at the end because 
-                // the cells are iterated even when they're too large
-               
-                if mm256_testz_ps(mask, mm256_set1_ps(-1f32)) == 1i32 {
+                let mag2 = zr.mul_add(zr, zi * zi);
+                let mask = mag2.lt(limit);
+                mk = mk + V::select(mask, one, zero);
+                if periodicity_check {
+                    let dr = zr - saved_r;
+                    let di = zi - saved_i;
+                    let cycling = dr.mul_add(dr, di * di).lt(period_tolerance);
+                    if !whole_orbit {
+                        limit = V::select(mask, V::select(cycling, zero, limit), limit);
+                        mk = V::select(mask, V::select(cycling, mk_inside, mk), mk);
+                    }
+                    if find_period {
+                        // z came back after since_saved + 1 steps. orbits that
+                        // keep going after that come back after multiples of
+                        // it too, so only the first one counts
+                        let steps = V::splat(cast(since_saved + 1).unwrap());
+                        let first = V::select(period_found.lt(one), steps, period_found);
+                        period_found = V::select(mask, V::select(cycling, first, period_found), period_found);
+                    }
+                    since_saved += 1;
+                    if since_saved == period {
+                        saved_r = zr;
+                        saved_i = zi;
+                        period *= 2;
+                        since_saved = 0;
+                    }
+                }
+                // save the magnitude at the iteration that escaped, which
+                // smooth_iter needs. we can't just use the magnitude at the
+                // end because the cells are iterated even when they're too
+                // large
+                mag2final = V::select(live, mag2, mag2final);
+                if want_final_z || want_angle {
+                    zr_final = V::select(live, zr, zr_final);
+                    zi_final = V::select(live, zi, zi_final);
+                }
+                if track_dz {
+                    dzr_final = V::select(live, dzr, dzr_final);
+                    dzi_final = V::select(live, dzi, dzi_final);
+                }
+                if let Some(ref trap) = trap {
+                    trap_min = V::select(mask, trap_min.min(trap.distance(zr, zi)), trap_min);
+                }
+                if want_min_modulus {
+                    min_mag2 = V::select(mask, min_mag2.min(mag2), min_mag2);
+                }
+                // up to and including the iteration that escaped
+                if let Some(ref average) = average {
+                    let term = average.term(zr, zi, prev_mag2, c_mod);
+                    sum = V::select(live, sum + term, sum);
+                    last = V::select(live, term, last);
+                    count = V::select(live, count + one, count);
+                }
+                live = mask;
+
+                if !mask.any() {
                     break;
                 }
 
             }
 
-            let mk = mk.as_f32x8().as_array();
-            let mag2final = mag2final.as_f32x8().as_array();
-            for i in 0..8 {
-                buf[(height - y - 1)*width + x + i] = if (mk[i] as u32) >= max_iterations {
+            // the multiplier is the product of 2z over the cycle. the orbits
+            // inside have ended up on their cycle, so go around it once more
+            let (mut mr, mut mi) = (one, zero);
+            if want_multiplier {
+                period_found.store(&mut period_out);
+                let longest = period_out[..V::LANES].iter().fold(0, |a, p| a.max(p.to_u32().unwrap()));
+                for k in 0..longest {
+                    let on_cycle = V::splat(cast(k).unwrap()).lt(period_found);
+                    let r = zr * mr - zi * mi;
+                    let i = zr.mul_add(mi, zi * mr);
+                    mr = V::select(on_cycle, r + r, mr);
+                    mi = V::select(on_cycle, i + i, mi);
+                    let (zr1, zi1) = formula.step(zr, zi, cr, ci, &mut state);
+                    zr = zr1;
+                    zi = zi1;
+                }
+                if bulb_check {
+                    // the bulb check didn't iterate those orbits, but their
+                    // cycles are known. the main cardioid has the fixed point
+                    // (1 - sqrt(1 - 4c))/2, and the period 2 bulb the
+                    // multiplier 4(c + 1)
+                    let (sr, si) = V::map2(one - four * cr, zero - four * ci, |x, y| {
+                        let r = x.hypot(y);
+                        let re = ((r + x) / two).sqrt();
+                        let im = ((r - x) / two).sqrt();
+                        (re, if y < V::Elem::zero() { -im } else { im })
+                    });
+                    let period_1 = period_found.lt(one + one);
+                    let known_r = V::select(period_1, one - sr, four * (cr + one));
+                    let known_i = V::select(period_1, zero - si, four * ci);
+                    let iterated = in_bulbs.lt(one);
+                    mr = V::select(iterated, mr, known_r);
+                    mi = V::select(iterated, mi, known_i);
+                }
+            }
+
+            mk.store(&mut mk_out);
+            mag2final.store(&mut mag2_out);
+            if find_period {
+                period_found.store(&mut period_out);
+            }
+            if trap.is_some() {
+                trap_min.store(&mut trap_out);
+            }
+            if average.is_some() {
+                sum.store(&mut sum_out);
+                last.store(&mut last_out);
+                count.store(&mut count_out);
+            }
+            if distance_estimate {
+                dzr_final.mul_add(dzr_final, dzi_final * dzi_final).store(&mut dz2_out);
+            }
+            let start = row*width + x;
+            for i in 0..n_lanes {
+                let mk = mk_out[i].to_f32().unwrap();
+                out.value[start + i] = if trap.is_some() {
+                    trap_out[i].to_f32().unwrap()
+                } else if (mk as u32) >= max_iterations {
                     -1f32
+                } else if distance_estimate {
+                    let d = distance(mag2_out[i].to_f64().unwrap(), dz2_out[i].to_f64().unwrap());
+                    (d / pixel) as f32
+                } else if average.is_some() {
+                    smooth_average(sum_out[i].to_f64().unwrap(), last_out[i].to_f64().unwrap(),
+                                   count_out[i].to_f64().unwrap(), mag2_out[i].to_f64().unwrap(),
+                                   cfg.bailout, cfg.formula.degree()) as f32
                 } else {
-                    smooth_iter(mk[i], mag2final[i])
+                    smooth_iter(mk + 1f32, mag2_out[i].to_f32().unwrap(), degree)
+                };
+            }
+
+            if let Some(ref mut final_z) = out.final_z {
+                store_complex(zr_final, zi_final, &mut final_z[start..start + n_lanes]);
+            }
+            // only the outside escaped
+            let escaped = |i: usize| (mk_out[i].to_f32().unwrap() as u32) < max_iterations;
+            if let Some(ref mut angle) = out.angle {
+                let mut z = [(0f32, 0f32); MAX_LANES];
+                store_complex(zr_final, zi_final, &mut z[..n_lanes]);
+                for i in 0..n_lanes {
+                    angle[start + i] = if escaped(i) { z[i].1.atan2(z[i].0) } else { std::f32::NAN };
+                }
+            }
+            if let Some(ref mut derivative) = out.derivative {
+                store_complex(dzr_final, dzi_final, &mut derivative[start..start + n_lanes]);
+            }
+            if let Some(ref mut min_modulus) = out.min_modulus {
+                store_real(min_mag2.sqrt(), &mut min_modulus[start..start + n_lanes]);
+            }
+            // only the inside has a period and a multiplier
+            let has_cycle = |i: usize| !escaped(i) && period_out[i] >= V::Elem::one();
+            if let Some(ref mut period) = out.period {
+                for i in 0..n_lanes {
+                    period[start + i] = if has_cycle(i) { period_out[i].to_u32().unwrap() } else { 0 };
+                }
+            }
+            if let Some(ref mut multiplier) = out.multiplier {
+                let multiplier = &mut multiplier[start..start + n_lanes];
+                store_complex(mr, mi, multiplier);
+                for i in 0..n_lanes {
+                    if !want_multiplier || !has_cycle(i) {
+                        multiplier[i] = (0f32, 0f32);
+                    }
                 }
             }
         }
     }
-    buf
 }
 
+// the first dst.len() lanes, as (re, im) pairs
+#[inline(always)]
+fn store_complex<V: Vector>(re: V, im: V, dst: &mut [(f32, f32)]) {
+    let mut re_out = [V::Elem::zero(); MAX_LANES];
+    let mut im_out = [V::Elem::zero(); MAX_LANES];
+    re.store(&mut re_out);
+    im.store(&mut im_out);
+    for (i, x) in dst.iter_mut().enumerate() {
+        *x = (re_out[i].to_f32().unwrap(), im_out[i].to_f32().unwrap());
+    }
+}
+
+// the first dst.len() lanes
+#[inline(always)]
+fn store_real<V: Vector>(xs: V, dst: &mut [f32]) {
+    let mut out = [V::Elem::zero(); MAX_LANES];
+    xs.store(&mut out);
+    for (i, x) in dst.iter_mut().enumerate() {
+        *x = out[i].to_f32().unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+// inverse.rs
+//
+// julia sets of z^2 + c drawn by running the map backwards. every z has two
+// preimages, +-sqrt(z - c), and going backwards pulls points towards the
+// julia set instead of away from it, so following preimages from a point on
+// the set plots the set itself. that works for every c, also the ones outside
+// the mandelbrot set where the julia set is dust and escape times leave
+// nothing to see. the image is how often each pixel was landed on, like a
+// buddhabrot
+//
+// plain inverse iteration (Formula::Iim) picks one of the two preimages at
+// random every step, and piles up on the parts of the set that are easy to
+// get to. the modified version (Formula::Miim) follows both, and stops going
+// down a branch once the pixel it's in has been hit often enough, which
+// fills in the parts that are hard to get to as well
+use FractalCfg;
+use formula::Formula;
+use fractal::PixelGrid;
+use buddhabrot::{Density, CHUNK_SAMPLES, chunk_rng};
+use num::Complex;
+use rand::Rng;
+use rayon::prelude::*;
+
+// steps every random walk takes before it's plotted. it starts on the set,
+// but at the same point every time
+const BURN_IN: usize = 32;
+
+// the repelling fixed point 1/2 + sqrt(1/4 - c), which is on the julia set
+// for every c
+fn fixed_point(c: Complex<f64>) -> Complex<f64> {
+    Complex::new(0.5, 0.0) + (Complex::new(0.25, 0.0) - c).sqrt()
+}
+
+// hit counts of the julia set of cfg.cr + cfg.ci i, in one channel
+pub fn inverse_iteration(cfg: &FractalCfg) -> Density {
+    let grid = PixelGrid::from_cfg(cfg);
+    let c = Complex::new(cfg.cr, cfg.ci);
+    let counts = match cfg.formula {
+        Formula::Miim(hits) => modified(cfg, &grid, c, hits),
+        _ => random_walk(cfg, &grid, c),
+    };
+    Density {
+        channels: vec![counts.into_iter().map(|x| x as f32).collect()],
+    }
+}
+
+// cfg.samples points per pixel, from random walks that each take
+// CHUNK_SAMPLES steps
+fn random_walk(cfg: &FractalCfg, grid: &PixelGrid, c: Complex<f64>) -> Vec<u32> {
+    let n_pixels = grid.len();
+    let n_samples = (cfg.samples as usize) * n_pixels;
+    let n_chunks = (n_samples + CHUNK_SAMPLES - 1) / CHUNK_SAMPLES;
+    (0..n_chunks).into_par_iter()
+        .fold(|| vec![0u32; n_pixels], |mut counts, chunk| {
+            let mut rng = chunk_rng(cfg.seed, chunk);
+            let n = CHUNK_SAMPLES.min(n_samples - chunk * CHUNK_SAMPLES);
+            let mut z = fixed_point(c);
+            for i in 0..(BURN_IN + n) {
+                z = (z - c).sqrt();
+                if rng.gen() {
+                    z = -z;
+                }
+                if i >= BURN_IN {
+                    if let Some(p) = grid.pixel(z.re, z.im) {
+                        counts[p] += 1;
+                    }
+                }
+            }
+            counts
+        })
+        // adding integers, so the order they get added in doesn't matter
+        .reduce(|| vec![0u32; n_pixels], |mut a, b| {
+            for (x, y) in a.iter_mut().zip(b.iter()) {
+                *x += *y;
+            }
+            a
+        })
+}
+
+// depth first through the tree of preimages, at most cfg.max_iterations
+// deep, leaving out the children of points that land on a pixel that's been
+// hit more than `hits` times. points outside the image are counted on a grid
+// as big as the image over the whole set, so branches that leave the image
+// get cut off too
+fn modified(cfg: &FractalCfg, grid: &PixelGrid, c: Complex<f64>, hits: u32) -> Vec<u32> {
+    let mut counts = vec![0u32; grid.len()];
+    // past this radius z^2 + c only gets bigger, so the set is inside it
+    let radius = 0.5 + (0.25 + c.norm()).sqrt();
+    let whole_set = PixelGrid::new(cfg.width as usize, cfg.height as usize, (0.0, 0.0), 2.0 / radius);
+    let mut outside_counts = vec![0u32; whole_set.len()];
+
+    let mut stack = vec![(fixed_point(c), 0)];
+    while let Some((z, depth)) = stack.pop() {
+        let count = match (grid.pixel(z.re, z.im), whole_set.pixel(z.re, z.im)) {
+            (Some(p), _) => &mut counts[p],
+            (None, Some(p)) => &mut outside_counts[p],
+            // only rounding gets a point out there
+            (None, None) => continue,
+        };
+        *count += 1;
+        if *count > hits || depth >= cfg.max_iterations {
+            continue;
+        }
+        let w = (z - c).sqrt();
+        stack.push((w, depth + 1));
+        stack.push((-w, depth + 1));
+    }
+    counts
+}
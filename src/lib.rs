@@ -1,15 +1,16 @@
 // lib.rs
-extern crate x86intrin;
 extern crate palette;
+extern crate num;
+extern crate rayon;
 extern crate bincode;
 extern crate imagefmt;
+extern crate rand;
 
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
 extern crate serde_json;
 
-#[macro_use]
 extern crate clap;
 use clap::ArgMatches;
 
@@ -24,14 +25,68 @@ use std::time::Instant;
 pub struct FractalCfg {
     pub width: u32, pub height: u32,
     pub max_iterations: u32,
+    // orbits that get further than this from 0 have escaped
+    pub bailout: f64,
     pub center_r: f64, pub center_i: f64,
+    // center as decimal strings, for the perturbation renderer which needs
+    // more digits than f64 has. empty means use center_r/center_i
+    pub center_r_str: String, pub center_i_str: String,
     pub zoom: f64,
     pub cr: f64, pub ci: f64,
     pub multiplier: f64,
     pub julia: bool,
+    pub formula: Formula,
+    // color by the closest the orbit gets to this, instead of the iteration
+    // count
+    pub trap: Option<Trap>,
+    // color by the average of something along the orbit, instead of the
+    // iteration count
+    pub average: Option<Average>,
+    // color by the estimated distance to the set, in pixels, instead of the
+    // iteration count
+    pub distance_estimate: bool,
+    // only used by Formula::Newton and Formula::Nova
+    pub polynomial: Polynomial,
+    // only used by Formula::Lyapunov, a string of A and B
+    pub sequence: String,
+    // only used by Formula::Buddhabrot and Formula::Iim: random points per
+    // pixel and the seed they're drawn with. and for buddhabrots only, the
+    // iteration limits of the red, green and blue channels for a nebulabrot
+    // (empty for one max_iterations channel)
+    pub samples: u32,
+    pub seed: u64,
+    pub channel_iterations: Vec<u32>,
     pub offset: f64,
     pub colormap: String,
+    // colormap for the negative half of signed fields, colormap does the
+    // positive half
+    pub negative_colormap: String,
+    // how to color the inside, and the colormap for it
+    pub interior: Interior,
+    pub interior_colormap: String,
+    // shading on top of the colors of the outside
+    pub exterior: Exterior,
+    // lights the value field like a height map
+    pub relief: Option<Relief>,
+    // external rays to draw over the image, and how deep to trace them at
+    // most
+    pub rays: Vec<ExternalAngle>,
+    pub ray_depth: u32,
     pub downsample: bool,
+    pub precision: Precision,
+    // skip the shared first iterations of a perturbation render
+    pub series_approximation: bool,
+    // reference orbits a perturbation render tries before it keeps the
+    // pixels that are still glitched
+    pub references: usize,
+    // skip c in the main cardioid and the period 2 bulb, which never escape
+    pub bulb_check: bool,
+    // stop iterating orbits that have settled into a cycle
+    pub periodicity_check: bool,
+    // render threads, 0 for one per core. doesn't change the output, so it's
+    // left out of the metadata file
+    #[serde(skip)]
+    pub threads: usize,
 }
 
 impl Default for FractalCfg {
@@ -39,43 +94,229 @@ impl Default for FractalCfg {
         FractalCfg{
             width: 800u32, height: 800u32,
             max_iterations: 256u32,
+            bailout: 256.0,
             center_r: 0.0, center_i: 0.0,
+            center_r_str: String::new(), center_i_str: String::new(),
             zoom: 1.0,
             cr: 0.0, ci: 0.0,
             multiplier: 1.0,
             julia: false,
+            formula: Formula::Mandelbrot,
+            trap: None,
+            average: None,
+            distance_estimate: false,
+            polynomial: Polynomial::default(),
+            sequence: "AB".to_owned(),
+            samples: 16,
+            seed: 0,
+            channel_iterations: vec![],
             offset: 0f64,
             colormap: "hot".to_owned(),
+            negative_colormap: "cosine".to_owned(),
+            interior: Interior::Black,
+            interior_colormap: "hsv".to_owned(),
+            exterior: Exterior::Plain,
+            relief: None,
+            rays: vec![],
+            ray_depth: 256,
             downsample: false,
+            precision: Precision::Auto,
+            series_approximation: true,
+            references: 32,
+            bulb_check: true,
+            periodicity_check: true,
+            threads: 0,
+        }
+    }
+}
+
+// floating point type the kernel iterates in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    // f32 while it can still resolve neighbouring pixels, then f64, then
+    // perturbation
+    Auto,
+    Single,
+    Double,
+    // arbitrary precision reference orbit, see perturbation.rs
+    Perturbation,
+}
+
+impl std::str::FromStr for Precision {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Precision, String> {
+        match s {
+            "auto"   => Ok(Precision::Auto),
+            "single" => Ok(Precision::Single),
+            "double" => Ok(Precision::Double),
+            "perturbation" => Ok(Precision::Perturbation),
+            _ => Err(format!("unknown precision {}", s)),
+        }
+    }
+}
+
+// coloring for points inside the set
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interior {
+    Black,
+    // a color per period of the cycle the orbit settles into, darker
+    // towards the edge of each component when the multiplier is known
+    Period,
+    // the angle of the cycle's multiplier, darker as it gets bigger. each
+    // component goes once around the colormap
+    Multiplier,
+}
+
+impl std::str::FromStr for Interior {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Interior, String> {
+        match s {
+            "black"  => Ok(Interior::Black),
+            "period" => Ok(Interior::Period),
+            "multiplier" => Ok(Interior::Multiplier),
+            _ => Err(format!("unknown interior coloring {}", s)),
         }
     }
 }
 
-pub trait FromMatches {
-    fn from_matches(matches: &ArgMatches) -> Self;
+// shading for points outside the set, by the angle z escaped at. z^(2^n)
+// goes around 2^n times as c goes once around the set, so the angles trace
+// out the external rays
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exterior {
+    Plain,
+    // binary decomposition, dark where im(z) < 0. every band of the
+    // iteration count splits into a checkerboard of the rays in between
+    Binary,
+    // dark along the rays through angle 0, lightening continuously away
+    // from them
+    FieldLines,
+}
+
+impl std::str::FromStr for Exterior {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Exterior, String> {
+        match s {
+            "plain"  => Ok(Exterior::Plain),
+            "binary" => Ok(Exterior::Binary),
+            "field-lines" => Ok(Exterior::FieldLines),
+            _ => Err(format!("unknown exterior coloring {}", s)),
+        }
+    }
+}
+
+pub trait FromMatches: Sized {
+    // an error for options that are given but don't parse, the ones left
+    // out are the defaults
+    fn from_matches(matches: &ArgMatches) -> Result<Self, String>;
+}
+
+// the value of an option, if it was given
+fn value<T>(matches: &ArgMatches, name: &str) -> Result<Option<T>, String>
+    where T: std::str::FromStr, T::Err: std::fmt::Display {
+    match matches.value_of(name) {
+        Some(s) => s.parse().map(Some).map_err(|e| format!("bad {} {:?}: {}", name, s, e)),
+        None => Ok(None),
+    }
+}
+
+// the values of an option that takes a list, if it was given
+fn values<T>(matches: &ArgMatches, name: &str) -> Result<Option<Vec<T>>, String>
+    where T: std::str::FromStr, T::Err: std::fmt::Display {
+    match matches.values_of(name) {
+        Some(xs) => xs.map(|s| s.parse().map_err(|e| format!("bad {} {:?}: {}", name, s, e)))
+            .collect::<Result<Vec<T>, String>>().map(Some),
+        None => Ok(None),
+    }
 }
 
 impl FromMatches for FractalCfg {
-    fn from_matches(matches: &ArgMatches) -> FractalCfg {
+    fn from_matches(matches: &ArgMatches) -> Result<FractalCfg, String> {
         let d = FractalCfg::default();
-        FractalCfg {
-            width: value_t!(matches, "width", u32).unwrap_or(d.width),
-            height: value_t!(matches, "height", u32).unwrap_or(d.height),
-            max_iterations: value_t!(matches, "iter", u32).unwrap_or(d.max_iterations),
-            center_r: value_t!(matches, "r", f64).unwrap_or(d.center_r),
-            center_i: value_t!(matches, "i", f64).unwrap_or(d.center_i),
-            zoom: value_t!(matches, "zoom", f64).unwrap_or(d.zoom),
-            cr: value_t!(matches, "cr", f64).unwrap_or(d.ci),
-            ci: value_t!(matches, "ci", f64).unwrap_or(d.ci),
-            multiplier: value_t!(matches, "multiplier", f64).unwrap_or(d.multiplier),
+        Ok(FractalCfg {
+            width: value(matches, "width")?.unwrap_or(d.width),
+            height: value(matches, "height")?.unwrap_or(d.height),
+            max_iterations: value(matches, "iter")?.unwrap_or(d.max_iterations),
+            bailout: value(matches, "bailout")?.unwrap_or(d.bailout),
+            center_r: value(matches, "r")?.unwrap_or(d.center_r),
+            center_i: value(matches, "i")?.unwrap_or(d.center_i),
+            center_r_str: matches.value_of("r").map(|s| s.to_owned()).unwrap_or(d.center_r_str),
+            center_i_str: matches.value_of("i").map(|s| s.to_owned()).unwrap_or(d.center_i_str),
+            zoom: value(matches, "zoom")?.unwrap_or(d.zoom),
+            cr: value(matches, "cr")?.unwrap_or(d.ci),
+            ci: value(matches, "ci")?.unwrap_or(d.ci),
+            multiplier: value(matches, "multiplier")?.unwrap_or(d.multiplier),
             julia: matches.is_present("julia"),
-            offset: value_t!(matches, "offset", f64).unwrap_or(d.offset),
-            colormap: value_t!(matches, "colormap", String).unwrap_or(d.colormap),
+            formula: value(matches, "formula")?.unwrap_or(d.formula),
+            trap: value(matches, "trap")?,
+            average: value(matches, "average")?,
+            distance_estimate: matches.is_present("distance"),
+            polynomial: value(matches, "polynomial")?.unwrap_or(d.polynomial),
+            sequence: value(matches, "sequence")?.unwrap_or(d.sequence),
+            samples: value(matches, "samples")?.unwrap_or(d.samples),
+            seed: value(matches, "seed")?.unwrap_or(d.seed),
+            channel_iterations: values(matches, "nebula")?.unwrap_or(d.channel_iterations),
+            offset: value(matches, "offset")?.unwrap_or(d.offset),
+            colormap: value(matches, "colormap")?.unwrap_or(d.colormap),
+            negative_colormap: value(matches, "negative_colormap")?.unwrap_or(d.negative_colormap),
+            interior: value(matches, "interior")?.unwrap_or(d.interior),
+            interior_colormap: value(matches, "interior_colormap")?.unwrap_or(d.interior_colormap),
+            exterior: value(matches, "exterior")?.unwrap_or(d.exterior),
+            relief: value(matches, "relief")?,
+            rays: values(matches, "rays")?.unwrap_or(d.rays),
+            ray_depth: value(matches, "ray_depth")?.unwrap_or(d.ray_depth),
             downsample: matches.is_present("downsample"),
-        }
+            precision: value(matches, "precision")?.unwrap_or(d.precision),
+            series_approximation: !matches.is_present("no_series"),
+            references: value(matches, "references")?.unwrap_or(d.references),
+            bulb_check: !matches.is_present("no_bulb_check"),
+            periodicity_check: !matches.is_present("no_periodicity"),
+            threads: value(matches, "threads")?.unwrap_or(d.threads),
+        })
     }
 }
 
+mod simd;
+mod backend;
+pub use backend::Backend;
+
+mod fixed;
+mod perturbation;
+
+mod formula;
+pub use formula::Formula;
+
+mod trap;
+pub use trap::Trap;
+
+mod average;
+pub use average::Average;
+
+mod relief;
+pub use relief::Relief;
+use relief::apply_shade;
+
+mod newton;
+pub use newton::{Polynomial, NewtonImage, newton};
+
+mod lyapunov;
+
+mod buddhabrot;
+pub use buddhabrot::{Density, buddhabrot, normalize_density};
+
+mod inverse;
+pub use inverse::inverse_iteration;
+
+mod nucleus;
+pub use nucleus::{Nucleus, Misiurewicz, find_period, find_nucleus, find_misiurewicz};
+
+mod rays;
+pub use rays::{ExternalAngle, Ray, trace_ray};
+use rays::draw_rays;
+
+mod render;
+pub use render::{Channels, Render, RenderRows};
+
 mod fractal;
 pub use fractal::*;
 
@@ -89,31 +330,58 @@ pub fn normalize(xs: Vec<f32>, mul: f32, offset: f32) -> Vec<f32> {
             if x < 0f32 {
                 -1f32
             } else {
-                let x = (x+1f32).log2();
-                let x = x * mul;
-                let x = x + offset;
-                // // div by eps+1 to make sure it is in range [0,1), not [0,1]
-                // let x = (0.5f32*(x * PI * 2f32).sin() + 0.5f32) / (1f32 + std::f32::EPSILON);
-                // triangle waves look better than sine waves, because the colors aren't bunched
-                // together
-                // TODO: user-selectable waves just like colormaps
-                let x = x % (1f32 + std::f32::EPSILON);
-                let x = if x < 0.5 {
-                    2.0*x
-                } else {
-                    2.0 - 2.0*x
-                };
+                wave(x, mul, offset)
+            }
+        })
+        .collect()
+}
+
+// like normalize, for fields where negative values are not the inside (see
+// Formula::is_signed). both halves are mapped by their magnitude and keep
+// their sign, -0.0 included, so check them with is_sign_negative. NaN stays
+// NaN
+pub fn normalize_signed(xs: Vec<f32>, mul: f32, offset: f32) -> Vec<f32> {
+    xs.into_iter()
+        .map(|x| {
+            if x.is_nan() {
                 x
+            } else if x < 0f32 {
+                -wave(-x, mul, offset)
+            } else {
+                wave(x, mul, offset)
             }
         })
         .collect()
 }
 
+// x >= 0 to [0,1]
+fn wave(x: f32, mul: f32, offset: f32) -> f32 {
+    let x = (x+1f32).log2();
+    let x = x * mul;
+    let x = x + offset;
+    // // div by eps+1 to make sure it is in range [0,1), not [0,1]
+    // let x = (0.5f32*(x * PI * 2f32).sin() + 0.5f32) / (1f32 + std::f32::EPSILON);
+    // triangle waves look better than sine waves, because the colors aren't bunched
+    // together
+    // TODO: user-selectable waves just like colormaps
+    let x = x % (1f32 + std::f32::EPSILON);
+    let x = if x < 0.5 {
+        2.0*x
+    } else {
+        2.0 - 2.0*x
+    };
+    x
+}
+
+
 
 
 
+// bin is the channels to write to a .bin next to the png, or None to not
+// write one
+pub fn write_fractal(cfg: &FractalCfg, output: &str, bin: Option<Channels>, quiet: bool) -> std::io::Result<()> {
 
-pub fn write_fractal(cfg: &FractalCfg, output: &str, write_bin: bool, quiet: bool) -> std::io::Result<()> {
+    cfg.validate().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
 
     let metadata_file_path = format!("{}.json", output);
     
@@ -128,6 +396,10 @@ pub fn write_fractal(cfg: &FractalCfg, output: &str, write_bin: bool, quiet: boo
         }
     }
 
+    if !quiet {
+        println!("backend: {:?} ({:?})", Backend::detect(), cfg.resolve_precision());
+    }
+
     let time = Instant::now();
     let cfg2 = if cfg.downsample {
         FractalCfg {
@@ -136,22 +408,88 @@ pub fn write_fractal(cfg: &FractalCfg, output: &str, write_bin: bool, quiet: boo
             .. cfg.clone()
         }
     } else {cfg.clone()};
-    let buf = mandelbrot(&cfg2);
+    if cfg.formula.is_density() {
+        let density = if cfg.formula == Formula::Buddhabrot { buddhabrot(&cfg2) } else { inverse_iteration(&cfg2) };
+        return write_density(cfg, density, output, bin.is_some(), quiet, time);
+    }
+    // newton fractals are colored by root, and only shaded by iterations
+    let (render, stats, roots) = if cfg.formula == Formula::Newton {
+        let image = newton(&cfg2);
+        (Render::from_value(image.iterations), RenderStats::default(), Some(image.roots))
+    } else {
+        let channels = bin.unwrap_or_default().union(cfg.channels());
+        let (render, stats) = render_with_stats(&cfg2, Backend::detect(), channels);
+        (render, stats, None)
+    };
 
     if !quiet {
         println!("render time: {}", duration_str(time.elapsed()));
-        println!("f32 max {:?}", buf.iter().cloned().fold(std::f32::NAN, f32::max));
-        println!("f32 min {:?}", buf.iter().cloned().fold(std::f32::NAN, f32::min));
+        if cfg.resolve_precision() == Precision::Perturbation {
+            println!("reference orbits: {}", stats.references);
+            println!("glitched pixels: {}", stats.glitched);
+            println!("iterations skipped by series approximation: {}", stats.series_skipped);
+        }
+        println!("f32 max {:?}", render.value.iter().cloned().fold(std::f32::NAN, f32::max));
+        println!("f32 min {:?}", render.value.iter().cloned().fold(std::f32::NAN, f32::min));
     }
-    if write_bin {
+    if bin.is_some() {
         let bin_file_path = format!("{}.bin", output);
         let mut binfile = File::create(bin_file_path)?;
-        binfile.write(&bincode::serialize(&buf, bincode::Infinite).unwrap())?;
+        binfile.write_all(&bincode::serialize(&render, bincode::Infinite).unwrap())?;
     }
+    let Render { value: buf, angle, period, multiplier, .. } = render;
 
     let time = Instant::now();
-    let buf = normalize(buf, cfg.multiplier as f32, cfg.offset as f32);
-    let buf = color_map_from_str(&cfg.colormap).colorize_buffer(buf);
+    // the lighting needs the field before it's normalized
+    let shade = cfg.relief.map(|relief| relief.shade(&buf, cfg2.width as usize));
+    let cmap = color_map_from_str(&cfg.colormap);
+    let mut buf = if cfg.formula.is_signed() {
+        let buf = normalize_signed(buf, cfg.multiplier as f32, cfg.offset as f32);
+        SignedColorMap {
+            negative: color_map_from_str(&cfg.negative_colormap),
+            positive: cmap,
+        }.colorize_buffer(buf)
+    } else {
+        let buf = normalize(buf, cfg.multiplier as f32, cfg.offset as f32);
+        match roots {
+            Some(roots) => RootColors::new(&*cmap, cfg.polynomial.roots.len()).colorize_buffer(&roots, buf),
+            None => {
+                let mut buf = cmap.colorize_buffer(buf);
+                match angle {
+                    Some(ref angle) if cfg.exterior != Exterior::Plain => ExteriorShading {
+                        exterior: cfg.exterior,
+                    }.paint(&mut buf, angle),
+                    _ => {},
+                }
+                match period {
+                    Some(ref period) if cfg.interior != Interior::Black => InteriorColors {
+                        interior: cfg.interior,
+                        cmap: color_map_from_str(&cfg.interior_colormap),
+                    }.paint(&mut buf, period, multiplier.as_ref().map(|m| &m[..])),
+                    _ => {},
+                }
+                buf
+            },
+        }
+    };
+    if let Some(ref shade) = shade {
+        apply_shade(&mut buf, shade);
+    }
+    if !cfg.rays.is_empty() {
+        // no deeper than a pixel of the final image
+        let grid = PixelGrid::from_cfg(&cfg2);
+        let pixel = grid.pixel_size() * if cfg.downsample { 2.0 } else { 1.0 };
+        let rays: Vec<Ray> = cfg.rays.iter().map(|&a| trace_ray(a, cfg.ray_depth, pixel)).collect();
+        if !quiet {
+            for ray in rays.iter() {
+                println!("ray {} lands at {:?} {:?}{}", ray.angle, ray.landing.0, ray.landing.1,
+                         if ray.exact { "" } else { " (roughly)" });
+            }
+        }
+        // as wide after downsampling as without it
+        let pen = if cfg.downsample { 2 } else { 1 };
+        draw_rays(&mut buf, cfg2.width as usize, cfg2.height as usize, &grid, &rays, pen);
+    }
     let buf = if cfg.downsample {
         downsample((cfg.width*2) as usize, (cfg.height*2) as usize, buf)
     } else {buf};
@@ -162,12 +500,57 @@ pub fn write_fractal(cfg: &FractalCfg, output: &str, write_bin: bool, quiet: boo
         println!("u8 min {:?}", buf.iter().cloned().min());
     }
     
+    write_image(cfg, output, buf, quiet)
+}
+
+// the rest of write_fractal for buddhabrots and inverse iteration, which have
+// a density per channel instead of one escape time field
+fn write_density(cfg: &FractalCfg, density: Density, output: &str, write_bin: bool, quiet: bool,
+                 time: Instant) -> std::io::Result<()> {
+    if !quiet {
+        println!("render time: {}", duration_str(time.elapsed()));
+        for channel in density.channels.iter() {
+            println!("f32 max {:?}", channel.iter().cloned().fold(std::f32::NAN, f32::max));
+        }
+    }
+    if write_bin {
+        let bin_file_path = format!("{}.bin", output);
+        let mut binfile = File::create(bin_file_path)?;
+        binfile.write_all(&bincode::serialize(&density.channels, bincode::Infinite).unwrap())?;
+    }
+
+    let time = Instant::now();
+    let (mul, offset) = (cfg.multiplier as f32, cfg.offset as f32);
+    let buf = if density.channels.len() == 1 {
+        color_map_from_str(&cfg.colormap).colorize_buffer(normalize_density(&density.channels[0], mul, offset))
+    } else {
+        // nebulabrot, the channels are red, green and blue
+        let channels: Vec<_> = density.channels.iter()
+            .map(|c| normalize_density(c, mul, offset))
+            .collect();
+        (0..channels[0].len())
+            .flat_map(|i| channels.iter().map(move |c| (c[i] * 256f32) as u8))
+            .collect()
+    };
+    let buf = if cfg.downsample {
+        downsample((cfg.width*2) as usize, (cfg.height*2) as usize, buf)
+    } else {buf};
+    if !quiet {
+        println!("colorize+normalize time: {}", duration_str(time.elapsed()));
+    }
+
+    write_image(cfg, output, buf, quiet)
+}
+
+// writes the png, and the metadata file next to it
+fn write_image(cfg: &FractalCfg, output: &str, buf: Vec<u8>, quiet: bool) -> std::io::Result<()> {
     let time = Instant::now();
     imagefmt::write(output, cfg.width as usize, cfg.height as usize, imagefmt::ColFmt::RGB, &buf, imagefmt::ColType::Auto).expect("error writing file");
     if !quiet {
         println!("png time: {}", duration_str(time.elapsed()));
     }
 
+    let metadata_file_path = format!("{}.json", output);
     let mut outfile = File::create(metadata_file_path)?;
     outfile.write_all(&serde_json::to_vec_pretty(&cfg)?)
 }
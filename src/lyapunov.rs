@@ -0,0 +1,44 @@
+// lyapunov.rs
+//
+// lyapunov fractals: the logistic map x' = r*x*(1 - x), with r taken from the
+// real part of the point (a) or the imaginary part (b) in the order the
+// sequence says, AB -> a, b, a, b, ...
+// every pixel gets the lyapunov exponent of its orbit
+//
+//   lambda = 1/N * sum ln|r_n * (1 - 2*x_n)|
+//
+// which is negative where the orbit settles into a cycle, and positive where
+// it's chaotic. the interesting part is a, b in [2, 4]
+use FractalCfg;
+use fractal::map_pixels;
+
+// starting point of every orbit, anything in (0, 1) works
+const X0: f64 = 0.5;
+
+pub fn lyapunov(cfg: &FractalCfg) -> Vec<f32> {
+    let sequence: Vec<bool> = cfg.sequence.chars().map(|c| c == 'B').collect();
+    // iterations thrown away so the orbit can settle before we start measuring
+    let warmup = cfg.max_iterations / 4;
+    let n = cfg.max_iterations.max(1);
+    map_pixels(cfg, |p| {
+        let (a, b) = (p.re, p.im);
+        // the map only keeps x in [0, 1] for rates in [0, 4]
+        if !(a >= 0.0 && a <= 4.0 && b >= 0.0 && b <= 4.0) {
+            return ::std::f32::NAN;
+        }
+        let rate = |k: u32| if sequence[k as usize % sequence.len()] { b } else { a };
+        let mut x = X0;
+        for k in 0..warmup {
+            x = rate(k) * x * (1.0 - x);
+        }
+        let mut sum = 0f64;
+        for k in warmup..(warmup + n) {
+            let r = rate(k);
+            // at x = 0.5 the derivative is 0, and the exponent -inf. cap
+            // it so one superstable step doesn't swallow the whole sum
+            sum += (r * (1.0 - 2.0 * x)).abs().max(1e-300).ln();
+            x = r * x * (1.0 - x);
+        }
+        (sum / (n as f64)) as f32
+    })
+}
@@ -5,7 +5,7 @@ extern crate imagefmt;
 extern crate serde_json;
 extern crate bincode;
 extern crate clap;
-use clap::{Arg, App};
+use clap::{Arg, App, ArgMatches, SubCommand};
 
 /*
 RUSTFLAGS="-C target-cpu=native" cargo build --bins --release && cp target/release/{fractals,julia_animation} .
@@ -28,6 +28,11 @@ fn main() {
              .default_value("256")
              .long("iter")
              )
+        .arg(Arg::with_name("bailout")
+             .help("escape radius, large for smooth colors and small for some traps")
+             .default_value("256")
+             .long("bailout")
+             )
         .arg(Arg::with_name("r")
              .help("real value of center point")
              .default_value("0")
@@ -81,6 +86,12 @@ fn main() {
              .long("bin")
              .takes_value(false)
              )
+        .arg(Arg::with_name("channels")
+             .help("channels to put in the bin besides the image: final-z, angle, derivative, min-modulus, period and multiplier (like final-z,period)")
+             .long("channels")
+             .takes_value(true)
+             .requires("bin")
+             )
         .arg(Arg::with_name("quiet")
              .help("supress info")
              .short("q")
@@ -92,16 +103,220 @@ fn main() {
              .long("cmap")
              .default_value("hot")
              )
+        .arg(Arg::with_name("negative_colormap")
+             .help("colormap for negative values, like stable points of --formula lyapunov")
+             .long("neg-cmap")
+             .default_value("cosine")
+             )
+        .arg(Arg::with_name("interior")
+             .help("coloring of the inside: black, period (of the cycle the orbit settles into) or multiplier (of that cycle)")
+             .long("interior")
+             .default_value("black")
+             )
+        .arg(Arg::with_name("interior_colormap")
+             .help("colormap for --interior period and multiplier")
+             .long("interior-cmap")
+             .default_value("hsv")
+             )
+        .arg(Arg::with_name("exterior")
+             .help("shading of the outside by the angle z escaped at: plain, binary (decomposition, by the sign of im(z)) or field-lines (along the external rays)")
+             .long("exterior")
+             .default_value("plain")
+             )
+        .arg(Arg::with_name("relief")
+             .help("light the image like a height map: <angle>,<height>,<ambient>, the direction the light comes from in degrees, how steep the slopes are and the brightness in the shadows (like 45,1,0.25)")
+             .long("relief")
+             .takes_value(true)
+             )
+        .arg(Arg::with_name("rays")
+             .help("external rays of the mandelbrot set to draw over the image, at angles in turns (like 1/3,2/3,1/7)")
+             .long("rays")
+             .takes_value(true)
+             .use_delimiter(true)
+             )
+        .arg(Arg::with_name("ray_depth")
+             .help("how deep to trace --rays and landing at most, in iterations. they stop sooner once they're within a pixel of the set")
+             .long("ray-depth")
+             .default_value("256")
+             )
+        .arg(Arg::with_name("precision")
+             .help("float precision of the kernel: auto, single, double or perturbation")
+             .long("precision")
+             .default_value("auto")
+             )
+        .arg(Arg::with_name("no_series")
+             .help("don't skip iterations with series approximation in deep zooms")
+             .long("no-series")
+             .takes_value(false)
+             )
+        .arg(Arg::with_name("references")
+             .help("reference orbits to try in deep zooms before giving up on glitched pixels")
+             .long("references")
+             .default_value("32")
+             )
+        .arg(Arg::with_name("no_bulb_check")
+             .help("iterate the main cardioid and period 2 bulb instead of skipping them")
+             .long("no-bulb-check")
+             .takes_value(false)
+             )
+        .arg(Arg::with_name("no_periodicity")
+             .help("don't stop iterating orbits that have become periodic")
+             .long("no-periodicity")
+             .takes_value(false)
+             )
+        .arg(Arg::with_name("threads")
+             .help("number of render threads, 0 for one per core")
+             .long("threads")
+             .default_value("0")
+             )
+        .arg(Arg::with_name("formula")
+             .help("formula to iterate: mandelbrot, multibrot:<power> for z^power + c, tricorn, multicorn:<power> for conj(z)^power + c, burningship, celtic, buffalo, perpendicular, phoenix:<p> for z^2 + c + p*z_(n-1), newton, nova:<relaxation>, lyapunov, buddhabrot, iim or miim:<hits> (julia sets by inverse iteration, plain or stopping at pixels hit that often)")
+             .long("formula")
+             .default_value("mandelbrot")
+             )
+        .arg(Arg::with_name("distance")
+             .help("color by estimated distance to the set (in pixels) instead of iterations")
+             .long("distance")
+             )
+        .arg(Arg::with_name("trap")
+             .help("color by distance to an orbit trap instead of iterations: point:<c>, circle:<c>,<radius>, cross:<c> or line:<c>,<angle>")
+             .long("trap")
+             .takes_value(true)
+             )
+        .arg(Arg::with_name("average")
+             .help("color by an average along the orbit instead of iterations: stripe:<density> or triangle")
+             .long("average")
+             .takes_value(true)
+             )
+        .arg(Arg::with_name("polynomial")
+             .help("polynomial for --formula newton and nova, as coefficients from the highest power down (coefs:1,0,0,-1 is z^3-1) or as roots (roots:1,i,-i)")
+             .long("poly")
+             .default_value("coefs:1,0,0,-1")
+             )
+        .arg(Arg::with_name("sequence")
+             .help("sequence of A and B for --formula lyapunov")
+             .long("seq")
+             .default_value("AB")
+             )
+        .arg(Arg::with_name("samples")
+             .help("random points per pixel for --formula buddhabrot and iim")
+             .long("samples")
+             .default_value("16")
+             )
+        .arg(Arg::with_name("seed")
+             .help("random seed for --formula buddhabrot and iim")
+             .long("seed")
+             .default_value("0")
+             )
+        .arg(Arg::with_name("nebula")
+             .help("iteration limits of the red, green and blue channels, for a nebulabrot with --formula buddhabrot (like 5000,500,50)")
+             .long("nebula")
+             .takes_value(true)
+             .use_delimiter(true)
+             )
         .arg(Arg::with_name("downsample")
              .help("downsample result from 4x resolution")
              .long("downsample")
              .takes_value(false)
              )
+        .subcommand(SubCommand::with_name("nucleus")
+             .about("find the minibrot closest to -r and -i, and print where to zoom to see it")
+             .arg(Arg::with_name("period")
+                  .help("period of the minibrot, or the lowest one in view (up to --iter) if left out")
+                  .long("period")
+                  .takes_value(true)
+                  )
+             )
+        .subcommand(SubCommand::with_name("misiurewicz")
+             .about("find the misiurewicz point closest to -r and -i, where z_(preperiod + period) = z_preperiod")
+             .arg(Arg::with_name("preperiod")
+                  .help("iterations from z_0 = 0 until the cycle")
+                  .long("preperiod")
+                  .required(true)
+                  .takes_value(true)
+                  )
+             .arg(Arg::with_name("period")
+                  .help("period of the cycle")
+                  .long("period")
+                  .required(true)
+                  .takes_value(true)
+                  )
+             )
+        .subcommand(SubCommand::with_name("landing")
+             .about("trace external rays of the mandelbrot set down to --ray-depth, and print where they land")
+             .arg(Arg::with_name("angles")
+                  .help("angles in turns, like 1/3 or 2/7")
+                  .required(true)
+                  .multiple(true)
+                  )
+             )
         .get_matches();
     
-    let cfg = FractalCfg::from_matches(&matches);
+    let cfg = FractalCfg::from_matches(&matches).unwrap_or_else(|e| fail(&e));
+    match matches.subcommand() {
+        ("nucleus", Some(sub)) => return nucleus(&cfg, sub),
+        ("misiurewicz", Some(sub)) => return misiurewicz(&cfg, sub),
+        ("landing", Some(sub)) => return landing(&cfg, sub),
+        _ => {},
+    }
     let output = matches.value_of("output").unwrap();
 
-    write_fractal(&cfg, &output, matches.is_present("bin"), matches.is_present("quiet")).unwrap()
+    let channels = match matches.value_of("channels").unwrap_or("").parse::<Channels>() {
+        Ok(channels) => channels,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let bin = if matches.is_present("bin") { Some(channels) } else { None };
+
+    if let Err(e) = write_fractal(&cfg, &output, bin, matches.is_present("quiet")) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn fail(e: &str) -> ! {
+    eprintln!("error: {}", e);
+    std::process::exit(1);
 }
 
+fn nucleus(cfg: &FractalCfg, matches: &ArgMatches) {
+    let center = cfg.center().unwrap_or_else(|e| fail(&e));
+    let period = match matches.value_of("period") {
+        Some(p) => p.parse::<u32>().unwrap_or_else(|_| fail(&format!("bad period {}", p))),
+        // half the height of the view
+        None => find_period(center, 2.0 / cfg.zoom, cfg.max_iterations)
+            .unwrap_or_else(|| fail("no minibrot in view, zoom out or raise --iter")),
+    };
+    let nucleus = find_nucleus(center, period)
+        .unwrap_or_else(|| fail(&format!("no nucleus of period {} found", period)));
+    println!("period {}", nucleus.period);
+    println!("center {:?} {:?}", nucleus.center.0, nucleus.center.1);
+    println!("zoom {:?}", nucleus.zoom());
+    println!("angle {:?}", nucleus.angle());
+    println!("-r={:?} -i={:?} --zoom {:?}", nucleus.center.0, nucleus.center.1, nucleus.zoom());
+}
+
+fn misiurewicz(cfg: &FractalCfg, matches: &ArgMatches) {
+    let arg = |name: &str| {
+        let x = matches.value_of(name).unwrap();
+        x.parse::<u32>().unwrap_or_else(|_| fail(&format!("bad {} {}", name, x)))
+    };
+    let point = find_misiurewicz(cfg.center().unwrap_or_else(|e| fail(&e)), arg("preperiod"), arg("period"))
+        .unwrap_or_else(|| fail("no misiurewicz point found"));
+    println!("preperiod {}", point.preperiod);
+    println!("period {}", point.period);
+    println!("point {:?} {:?}", point.point.0, point.point.1);
+    println!("-r={:?} -i={:?}", point.point.0, point.point.1);
+}
+
+fn landing(cfg: &FractalCfg, matches: &ArgMatches) {
+    for x in matches.values_of("angles").unwrap() {
+        let angle = x.parse::<ExternalAngle>().unwrap_or_else(|e| fail(&e));
+        let (preperiod, period) = angle.preperiod_period();
+        let ray = trace_ray(angle, cfg.ray_depth, 0.0);
+        println!("{} preperiod {} period {} lands at {:?} {:?}{}", angle, preperiod, period,
+                 ray.landing.0, ray.landing.1, if ray.exact { "" } else { " (roughly)" });
+    }
+}
@@ -0,0 +1,240 @@
+// newton.rs
+//
+// newton's method z' = z - p(z)/p'(z) for a polynomial p. every pixel is a
+// starting point, and gets the index of the root it converges to along with
+// a smooth count of the steps it took.
+//
+// also the nova fractal z' = z - R*p(z)/p'(z) + c, which converges to fixed
+// points that move around with c instead of to the roots
+use {FractalCfg, Formula};
+use fractal::map_pixels;
+use num::Complex;
+
+// a step smaller than this counts as converged
+const TOLERANCE: f64 = 1e-6;
+// how close to a root the converged point has to be to count as that root
+const ROOT_TOLERANCE: f64 = 1e-3;
+
+// coefficients are stored highest power first, so [1, 0, 0, -1] is z^3 - 1.
+// complex numbers are (re, im) pairs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Polynomial {
+    pub coefficients: Vec<(f64, f64)>,
+    pub roots: Vec<(f64, f64)>,
+}
+
+impl Polynomial {
+    pub fn from_roots(roots: &[(f64, f64)]) -> Polynomial {
+        // multiply out (z - r_0)(z - r_1)...
+        let mut coefs = vec![Complex::new(1.0, 0.0)];
+        for &(re, im) in roots {
+            let r = Complex::new(re, im);
+            let mut next = coefs.clone();
+            next.push(Complex::new(0.0, 0.0));
+            for (i, &c) in coefs.iter().enumerate() {
+                next[i + 1] = next[i + 1] - c * r;
+            }
+            coefs = next;
+        }
+        Polynomial {
+            coefficients: coefs.iter().map(|c| (c.re, c.im)).collect(),
+            roots: roots.to_vec(),
+        }
+    }
+
+    pub fn from_coefficients(coefficients: &[(f64, f64)]) -> Result<Polynomial, String> {
+        let coefs: Vec<_> = coefficients.iter()
+            .map(|&(re, im)| Complex::new(re, im))
+            .skip_while(|c| c.norm_sqr() == 0.0)
+            .collect();
+        if coefs.len() < 2 {
+            return Err("polynomial needs at least one root".to_owned());
+        }
+        let roots = durand_kerner(&coefs);
+        Ok(Polynomial {
+            coefficients: coefs.iter().map(|c| (c.re, c.im)).collect(),
+            roots: roots.iter().map(|c| (c.re, c.im)).collect(),
+        })
+    }
+
+    // the fields are public and come back from saved configs, so an empty
+    // list is degree 0 rather than an underflow
+    pub fn degree(&self) -> usize {
+        self.coefficients.len().saturating_sub(1)
+    }
+}
+
+impl Default for Polynomial {
+    // z^3 - 1
+    fn default() -> Polynomial {
+        let s = 3f64.sqrt() / 2.0;
+        Polynomial::from_roots(&[(1.0, 0.0), (-0.5, s), (-0.5, -s)])
+    }
+}
+
+// coefs:1,0,0,-1 or roots:1,-0.5+0.866i,-0.5-0.866i
+impl ::std::str::FromStr for Polynomial {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Polynomial, String> {
+        let parse_list = |s: &str| s.split(',')
+            .map(parse_complex)
+            .collect::<Result<Vec<_>, String>>();
+        if s.starts_with("coefs:") {
+            Polynomial::from_coefficients(&parse_list(&s[6..])?)
+        } else if s.starts_with("roots:") {
+            let roots = parse_list(&s[6..])?;
+            if roots.is_empty() {
+                return Err("polynomial needs at least one root".to_owned());
+            }
+            Ok(Polynomial::from_roots(&roots))
+        } else {
+            Err(format!("bad polynomial {}, expected coefs:... or roots:...", s))
+        }
+    }
+}
+
+// 1, -2.5, i, -0.5i, 1+2i, -0.5-0.866i
+pub(crate) fn parse_complex(s: &str) -> Result<(f64, f64), String> {
+    let err = || format!("bad complex number {}", s);
+    let s = s.trim();
+    if !s.ends_with('i') {
+        return s.parse().map(|re| (re, 0.0)).map_err(|_| err());
+    }
+    let body = &s[..s.len() - 1];
+    // split before the sign of the imaginary part, skipping a leading sign
+    // and the sign of an exponent
+    let split = body.char_indices()
+        .filter(|&(i, c)| i > 0 && (c == '+' || c == '-') && !body[..i].ends_with(|c| c == 'e' || c == 'E'))
+        .map(|(i, _)| i)
+        .last();
+    let (re, im) = match split {
+        Some(i) => (&body[..i], &body[i..]),
+        None => ("0", body),
+    };
+    let im = match im {
+        "" | "+" => 1.0,
+        "-" => -1.0,
+        x => x.parse().map_err(|_| err())?,
+    };
+    Ok((re.parse().map_err(|_| err())?, im))
+}
+
+// all roots at once, for polynomials given by their coefficients
+fn durand_kerner(coefs: &[Complex<f64>]) -> Vec<Complex<f64>> {
+    let lead = coefs[0];
+    let monic: Vec<_> = coefs.iter().map(|&c| c / lead).collect();
+    let n = monic.len() - 1;
+    // the usual starting points, powers of something that isn't a root of
+    // unity or real
+    let seed = Complex::new(0.4, 0.9);
+    let mut roots: Vec<_> = (0..n).map(|k| seed.powi(k as i32)).collect();
+    for _ in 0..1000 {
+        let mut change = 0f64;
+        for i in 0..n {
+            let (p, _) = eval(&monic, roots[i]);
+            let mut den = Complex::new(1.0, 0.0);
+            for j in 0..n {
+                if j != i {
+                    den = den * (roots[i] - roots[j]);
+                }
+            }
+            let step = p / den;
+            roots[i] = roots[i] - step;
+            change = change.max(step.norm());
+        }
+        if change < 1e-15 {
+            break;
+        }
+    }
+    roots
+}
+
+// p(z) and p'(z) by horner's rule
+#[inline]
+fn eval(coefs: &[Complex<f64>], z: Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+    let mut p = Complex::new(0.0, 0.0);
+    let mut dp = Complex::new(0.0, 0.0);
+    for &c in coefs {
+        dp = dp * z + p;
+        p = p * z + c;
+    }
+    (p, dp)
+}
+
+// root index of every pixel, -1 where it didn't converge, and the smoothed
+// number of steps it took (-1 where it didn't converge)
+pub struct NewtonImage {
+    pub roots: Vec<i32>,
+    pub iterations: Vec<f32>,
+}
+
+pub fn newton(cfg: &FractalCfg) -> NewtonImage {
+    let coefs = complex_vec(&cfg.polynomial.coefficients);
+    let roots = complex_vec(&cfg.polynomial.roots);
+    let zero = Complex::new(0.0, 0.0);
+    let pixels = map_pixels(cfg, |z| {
+        let (z, iterations) = match converge(&coefs, 1.0, z, zero, cfg.max_iterations) {
+            Some(x) => x,
+            None => return (-1, -1f32),
+        };
+        let nearest = roots.iter()
+            .enumerate()
+            .map(|(i, r)| (i, (z - r).norm()))
+            .fold((0, ::std::f64::INFINITY), |a, b| if b.1 < a.1 { b } else { a });
+        if nearest.1 > ROOT_TOLERANCE {
+            (-1, -1f32)
+        } else {
+            (nearest.0 as i32, iterations)
+        }
+    });
+    NewtonImage {
+        roots: pixels.iter().map(|p| p.0).collect(),
+        iterations: pixels.iter().map(|p| p.1).collect(),
+    }
+}
+
+// smoothed number of steps to converge, -1 where it didn't
+pub fn nova(cfg: &FractalCfg) -> Vec<f32> {
+    let relaxation = match cfg.formula {
+        Formula::Nova(r) => r,
+        _ => 1.0,
+    };
+    let coefs = complex_vec(&cfg.polynomial.coefficients);
+    let c0 = Complex::new(cfg.cr, cfg.ci);
+    // the roots of p are critical points of the newton map, so the mandelbrot
+    // version starts every pixel from one of them
+    let z0 = complex_vec(&cfg.polynomial.roots)[0];
+    map_pixels(cfg, |p| {
+        let (z, c) = if cfg.julia { (p, c0) } else { (z0, p) };
+        converge(&coefs, relaxation, z, c, cfg.max_iterations)
+            .map_or(-1f32, |(_, iterations)| iterations)
+    })
+}
+
+fn complex_vec(xs: &[(f64, f64)]) -> Vec<Complex<f64>> {
+    xs.iter().map(|&(re, im)| Complex::new(re, im)).collect()
+}
+
+// iterates z' = z - relaxation * p(z)/p'(z) + c until the steps get smaller
+// than TOLERANCE, and returns where it ended up and the smoothed step count
+fn converge(coefs: &[Complex<f64>], relaxation: f64, z: Complex<f64>, c: Complex<f64>,
+            max_iterations: u32) -> Option<(Complex<f64>, f32)> {
+    let mut z = z;
+    for k in 0..max_iterations {
+        let (p, dp) = eval(coefs, z);
+        let z1 = z - p / dp * relaxation + c;
+        if !(z1.re.is_finite() && z1.im.is_finite()) {
+            // hit a critical point
+            return None;
+        }
+        let d = (z1 - z).norm();
+        z = z1;
+        if d < TOLERANCE {
+            // convergence is quadratic, so log(d) roughly doubles every step
+            // near the root, which makes this continuous across step counts
+            let smooth = (k + 1) as f64 - (d.ln() / TOLERANCE.ln()).log2();
+            return Some((z, smooth.max(0.0) as f32));
+        }
+    }
+    None
+}
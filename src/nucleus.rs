@@ -0,0 +1,216 @@
+// nucleus.rs
+//
+// finds the exact spots worth zooming into, from a rough guess. a minibrot
+// is a small copy of the mandelbrot set, and its nucleus, the center of its
+// cardioid, is the c where 0 is periodic: z_p(c) = 0 with z_0 = 0. a
+// misiurewicz point is where 0 ends up on a repelling cycle instead, after
+// some iterations, z_(q+p)(c) = z_q(c), and it's the center of a spiral.
+// both are found with newton's method on c, in f64, so they're good down to
+// zooms of about 1e13
+use FractalCfg;
+use num::Complex;
+
+// newton steps before giving up
+const MAX_STEPS: usize = 100;
+// relative distance at which two points of an orbit are taken to be the
+// same, when checking what was found
+const TOLERANCE: f64 = 1e-9;
+// steps smaller than this many ulps of c are rounding
+const ROUNDING: f64 = 64.0 * std::f64::EPSILON;
+
+// a minibrot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Nucleus {
+    pub period: u32,
+    // (re, im)
+    pub center: (f64, f64),
+    // how big the minibrot is next to the whole set, and which way it's
+    // turned, as the complex number that takes the whole set onto it
+    pub size: (f64, f64),
+}
+
+impl Nucleus {
+    // the zoom that shows the minibrot the way zoom 1 shows the whole set
+    pub fn zoom(&self) -> f64 {
+        1.0 / self.size.0.hypot(self.size.1)
+    }
+
+    // degrees counterclockwise the minibrot is turned from the whole set
+    pub fn angle(&self) -> f64 {
+        self.size.1.atan2(self.size.0).to_degrees()
+    }
+
+    // cfg, looking at the minibrot
+    pub fn view(&self, cfg: &FractalCfg) -> FractalCfg {
+        FractalCfg {
+            center_r: self.center.0,
+            center_i: self.center.1,
+            center_r_str: String::new(),
+            center_i_str: String::new(),
+            zoom: self.zoom(),
+            julia: false,
+            .. cfg.clone()
+        }
+    }
+}
+
+// a misiurewicz point, where z_(preperiod + period) = z_preperiod
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Misiurewicz {
+    pub preperiod: u32,
+    pub period: u32,
+    pub point: (f64, f64),
+}
+
+fn same(a: Complex<f64>, b: Complex<f64>) -> bool {
+    (a - b).norm() <= TOLERANCE * (1.0 + a.norm())
+}
+
+// z_0 up to z_n of c
+fn orbit(c: Complex<f64>, n: u32) -> Vec<Complex<f64>> {
+    let mut z = Complex::new(0.0, 0.0);
+    let mut orbit = vec![z];
+    for _ in 0..n {
+        z = z * z + c;
+        orbit.push(z);
+    }
+    orbit
+}
+
+// newton's method on f, which gives f(c) and f'(c). it's done once the step
+// is down to rounding, or once f(c) stops getting smaller with the steps
+// already tiny, which is as close as f64 gets when f(c) has a large
+// derivative. None if it doesn't settle down
+fn newton<F>(guess: Complex<f64>, f: F) -> Option<Complex<f64>>
+    where F: Fn(Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+    let mut c = guess;
+    let mut residual = std::f64::INFINITY;
+    for _ in 0..MAX_STEPS {
+        let (y, dy) = f(c);
+        let step = y / dy;
+        if !step.re.is_finite() || !step.im.is_finite() {
+            return None;
+        }
+        let tiny = step.norm() <= TOLERANCE * c.norm().max(1e-300);
+        if tiny && y.norm() >= residual {
+            return Some(c);
+        }
+        residual = y.norm();
+        c = c - step;
+        if step.norm() <= 4.0 * std::f64::EPSILON * c.norm().max(1e-300) {
+            return Some(c);
+        }
+    }
+    None
+}
+
+// newton's step for z_p(c) = 0 with z_0 = 0, how far c is from being a
+// nucleus of period p
+fn nucleus_step(c: Complex<f64>, p: u32) -> f64 {
+    let (mut z, mut dz) = (Complex::new(0.0, 0.0), Complex::new(0.0, 0.0));
+    for _ in 0..p {
+        dz = z * dz * 2.0 + 1.0;
+        z = z * z + c;
+    }
+    (z / dz).norm()
+}
+
+// the lowest period of a nucleus in the disk around center, up to
+// max_period. the disk is iterated as a whole, and the first time it covers
+// 0 again is the period of the closest nucleus
+pub fn find_period(center: (f64, f64), radius: f64, max_period: u32) -> Option<u32> {
+    let c = Complex::new(center.0, center.1);
+    // z_n of the disk is inside the disk around z_n of the center with
+    // radius r
+    let mut z = Complex::new(0.0, 0.0);
+    let mut r = 0.0;
+    for n in 1..(max_period + 1) {
+        // |(z + d)^2 + c + e - (z^2 + c)| <= 2|z||d| + |d|^2 + |e|
+        r = (2.0 * z.norm() + r) * r + radius;
+        z = z * z + c;
+        if z.norm() < r {
+            return Some(n);
+        }
+        // the disk has escaped
+        if z.norm() - r > 2.0 {
+            return None;
+        }
+    }
+    None
+}
+
+// the nucleus of period `period` closest to guess. newton's method can also
+// land on a nucleus whose period divides `period`, or from far away on a big
+// component like the period 2 bulb, and then there's None
+pub fn find_nucleus(guess: (f64, f64), period: u32) -> Option<Nucleus> {
+    if period == 0 {
+        return None;
+    }
+    let c = newton(Complex::new(guess.0, guess.1), |c| {
+        // z_p and dz_p/dc
+        let (mut z, mut dz) = (Complex::new(0.0, 0.0), Complex::new(0.0, 0.0));
+        for _ in 0..period {
+            dz = z * dz * 2.0 + 1.0;
+            z = z * z + c;
+        }
+        (z, dz)
+    })?;
+    let orbit = orbit(c, period);
+
+    // the size estimate: with l_i the derivative of the cycle up to z_i and
+    // b = the sum of 1/l_i, the minibrot is about 1/(b l_p^2) times the size
+    // of the whole set, turned by its argument
+    let (mut l, mut b) = (Complex::new(1.0, 0.0), Complex::new(1.0, 0.0));
+    for z in orbit[1..period as usize].iter() {
+        l = l * z * 2.0;
+        b = b + l.inv();
+    }
+    let size = (b * l * l).inv();
+
+    // z_p itself is only 0 to within its derivative times the rounding of c,
+    // which gets large with the period, so the periods are checked by how far
+    // c is from a nucleus instead. a nucleus of a lower period would make the
+    // size 0, and then only rounding is close enough
+    let close = (TOLERANCE * size.norm()).max(ROUNDING * c.norm());
+    let found = (1..(period + 1))
+        .find(|&p| period % p == 0 && nucleus_step(c, p) <= close)?;
+    if found != period {
+        return None;
+    }
+    Some(Nucleus { period: period, center: (c.re, c.im), size: (size.re, size.im) })
+}
+
+// the misiurewicz point with z_(preperiod + period) = z_preperiod closest to
+// guess, counting from z_0 = 0 (so c = -2 has preperiod 2 and period 1).
+// newton's method can also land on one with a lower preperiod or period,
+// which it then comes back with, but not on a nucleus
+pub fn find_misiurewicz(guess: (f64, f64), preperiod: u32, period: u32) -> Option<Misiurewicz> {
+    if period == 0 {
+        return None;
+    }
+    let n = preperiod + period;
+    let c = newton(Complex::new(guess.0, guess.1), |c| {
+        // z_(q+p) - z_q and its derivative
+        let (mut z, mut dz) = (Complex::new(0.0, 0.0), Complex::new(0.0, 0.0));
+        let (mut zq, mut dzq) = (z, dz);
+        for i in 0..n {
+            if i == preperiod {
+                zq = z;
+                dzq = dz;
+            }
+            dz = z * dz * 2.0 + 1.0;
+            z = z * z + c;
+        }
+        (z - zq, dz - dzq)
+    })?;
+    let orbit = orbit(c, n);
+    let q = preperiod as usize;
+    let period = (1..(period + 1))
+        .find(|&p| period % p == 0 && same(orbit[q + p as usize], orbit[q]))?;
+    let p = period as usize;
+    let preperiod = (0..(q + 1)).find(|&q| same(orbit[q + p], orbit[q]))?;
+    if preperiod == 0 {
+        return None;
+    }
+    Some(Misiurewicz { preperiod: preperiod as u32, period: period, point: (c.re, c.im) })
+}
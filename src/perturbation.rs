@@ -0,0 +1,315 @@
+// perturbation.rs
+//
+// deep zoom renderer. one reference orbit is iterated in arbitrary precision,
+// and every pixel only iterates its (small) difference to that orbit in f64:
+//
+//   z = Z + dz,  c = C + dc
+//   dz' = 2*Z*dz + dz^2 + dc
+//
+// this stops working when z gets close to zero while Z doesn't (the
+// "glitch"), in which case the pixel is thrown back and re-rendered against a
+// new reference picked from inside the glitch.
+// dc is kept in f64, so this goes down to about 1e-300
+//
+// the first iterations are nearly the same for every pixel in the frame, and
+// are skipped with a series approximation (see Series)
+use FractalCfg;
+use fixed::Fixed;
+use fractal::{calc_width, smooth_iter, RenderStats};
+use num::Complex;
+use rayon::prelude::*;
+
+// pixels with |z|^2 < GLITCH_TOLERANCE * |Z|^2 lost too much precision
+const GLITCH_TOLERANCE: f64 = 1e-6;
+// number of terms in the series approximation
+const SERIES_TERMS: usize = 8;
+// relative error in dz at which the series is no longer trusted
+const SERIES_TOLERANCE: f64 = 1e-8;
+
+struct Reference {
+    // position of the reference point relative to the image center
+    offset: (f64, f64),
+    // orbit[k] is z_(k+1), so orbit[0] is the starting point
+    orbit: Vec<(f64, f64)>,
+    // the reference's c, rounded to f64
+    c: (f64, f64),
+}
+
+// truncated power series dz = sum_k a_k * d^k in the pixel offset d (which is
+// dc for the mandelbrot set, and dz_1 for julia sets), valid for the first
+// `skip` iterations. the coefficients are stored as a_k * radius^k and
+// evaluated at d/radius, because at deep zooms d^k underflows long before
+// a_k*d^k does
+struct Series {
+    skip: usize,
+    radius: f64,
+    coefs: [Complex<f64>; SERIES_TERMS],
+}
+
+impl Series {
+    // dz_1 = d, so a_1 = 1
+    fn new(radius: f64) -> Series {
+        let mut coefs = [Complex::new(0.0, 0.0); SERIES_TERMS];
+        coefs[0] = Complex::new(radius, 0.0);
+        Series { skip: 0, radius: radius, coefs: coefs }
+    }
+
+    // one more iteration of dz' = 2*Z*dz + dz^2 + dc, collecting powers of d
+    fn step(&self, z: (f64, f64), julia: bool) -> Series {
+        let z = Complex::new(z.0, z.1);
+        let mut coefs = [Complex::new(0.0, 0.0); SERIES_TERMS];
+        for k in 0..SERIES_TERMS {
+            let mut sq = Complex::new(0.0, 0.0);
+            for i in 0..k {
+                sq = sq + self.coefs[i] * self.coefs[k - 1 - i];
+            }
+            coefs[k] = z * self.coefs[k] * 2.0 + sq;
+        }
+        if !julia {
+            coefs[0] = coefs[0] + self.radius;
+        }
+        Series { skip: self.skip + 1, radius: self.radius, coefs: coefs }
+    }
+
+    fn eval(&self, d: (f64, f64)) -> (f64, f64) {
+        let u = Complex::new(d.0, d.1) / self.radius;
+        // horner, the series has no constant term
+        let mut dz = Complex::new(0.0, 0.0);
+        for a in self.coefs.iter().rev() {
+            dz = (dz + a) * u;
+        }
+        (dz.re, dz.im)
+    }
+}
+
+enum Pixel {
+    Done(f32),
+    // ratio |z|^2 / |Z|^2 when the glitch was detected, smaller is worse
+    Glitch(f64),
+}
+
+// enough bits for the pixel spacing, plus some to spare for the iteration
+pub(crate) fn precision_bits(cfg: &FractalCfg) -> usize {
+    let pixels = cfg.width.max(cfg.height) as f64;
+    let bits = (cfg.zoom * pixels).log2().max(0.0) as usize;
+    bits + 64
+}
+
+pub(crate) fn parse_center(cfg: &FractalCfg, bits: usize) -> Result<(Fixed, Fixed), String> {
+    let parse = |s: &String, fallback: f64| if s.is_empty() {
+        Ok(Fixed::from_f64(fallback, bits))
+    } else {
+        Fixed::parse(s, bits).map_err(|_| format!("bad center {:?}", s))
+    };
+    Ok((parse(&cfg.center_r_str, cfg.center_r)?, parse(&cfg.center_i_str, cfg.center_i)?))
+}
+
+fn reference_orbit(cfg: &FractalCfg, center: &(Fixed, Fixed), offset: (f64, f64), threshold: f64) -> Reference {
+    let bits = precision_bits(cfg);
+    let pr = center.0.add(&Fixed::from_f64(offset.0, bits));
+    let pi = center.1.add(&Fixed::from_f64(offset.1, bits));
+    let (cr, ci) = if cfg.julia {
+        (Fixed::from_f64(cfg.cr, bits), Fixed::from_f64(cfg.ci, bits))
+    } else {
+        (pr.clone(), pi.clone())
+    };
+    let (mut zr, mut zi) = (pr, pi);
+
+    let mut orbit = Vec::with_capacity(cfg.max_iterations as usize + 1);
+    loop {
+        let z = (zr.to_f64(), zi.to_f64());
+        orbit.push(z);
+        if orbit.len() > cfg.max_iterations as usize || z.0*z.0 + z.1*z.1 >= threshold {
+            break;
+        }
+        let zr2 = zr.mul(&zr);
+        let zi2 = zi.mul(&zi);
+        zi = zr.mul(&zi).mul2().add(&ci);
+        zr = zr2.sub(&zi2).add(&cr);
+    }
+    Reference { offset: offset, orbit: orbit, c: (cr.to_f64(), ci.to_f64()) }
+}
+
+#[inline]
+fn step_delta(z: (f64, f64), dz: (f64, f64), dc: (f64, f64)) -> (f64, f64) {
+    let (zr, zi) = z;
+    let (dzr, dzi) = dz;
+    (
+        2.0*(zr*dzr - zi*dzi) + dzr*dzr - dzi*dzi + dc.0,
+        2.0*(zr*dzi + zi*dzr) + 2.0*dzr*dzi + dc.1,
+    )
+}
+
+// finds how many iterations the series stays accurate for, by iterating a
+// few probe pixels around the edge of the image next to it. pixels further
+// in have smaller d, and so a smaller truncation error than the probes
+fn series_approximation(reference: &Reference, julia: bool, radius: f64,
+                        probes: &[(f64, f64)], threshold: f64) -> Series {
+    let orbit = &reference.orbit;
+    let mut probe_dz = probes.to_vec();
+    let mut series = Series::new(radius);
+    for n in 0..(orbit.len().saturating_sub(2)) {
+        let next = series.step(orbit[n], julia);
+        let z = orbit[n + 1];
+        for (dz, &d) in probe_dz.iter_mut().zip(probes.iter()) {
+            let dc = if julia { (0.0, 0.0) } else { d };
+            *dz = step_delta(orbit[n], *dz, dc);
+            let approx = next.eval(d);
+            let err = (approx.0 - dz.0).hypot(approx.1 - dz.1);
+            let (xr, xi) = (z.0 + dz.0, z.1 + dz.1);
+            if err > SERIES_TOLERANCE * dz.0.hypot(dz.1) || xr*xr + xi*xi >= threshold {
+                return series;
+            }
+        }
+        series = next;
+    }
+    series
+}
+
+// same iteration count and smoothing as fractal::escape_time. dz is the
+// delta at iteration `start`, ie. the orbit entry orbit[start]
+fn iterate_pixel(reference: &Reference, start: usize, dz: (f64, f64), dc: (f64, f64),
+                 max_iterations: u32, threshold: f64, detect_glitches: bool) -> Pixel {
+    let orbit = &reference.orbit;
+    let mut dz = dz;
+    let mut mk = 1 + start as u32;
+    let mut mag2final = {
+        let (zr, zi) = orbit[start];
+        (zr + dz.0).powi(2) + (zi + dz.1).powi(2)
+    };
+    for k in start..(max_iterations as usize) {
+        if k + 1 >= orbit.len() {
+            // the reference escaped before this pixel did
+            if detect_glitches {
+                return Pixel::Glitch(1.0);
+            }
+            // and there's no reference left to try, so carry on with the
+            // pixel itself in f64, like a double render would
+            let (mut zr, mut zi) = (orbit[k].0 + dz.0, orbit[k].1 + dz.1);
+            let (cr, ci) = (reference.c.0 + dc.0, reference.c.1 + dc.1);
+            for _ in k..(max_iterations as usize) {
+                let zr2 = zr*zr - zi*zi;
+                zi = 2.0*zr*zi + ci;
+                zr = zr2 + cr;
+                mag2final = zr*zr + zi*zi;
+                if mag2final < threshold {
+                    mk += 1;
+                } else {
+                    break;
+                }
+            }
+            break;
+        }
+        dz = step_delta(orbit[k], dz, dc);
+
+        let (zr, zi) = orbit[k + 1];
+        let (xr, xi) = (zr + dz.0, zi + dz.1);
+        let mag2 = xr*xr + xi*xi;
+        // the magnitude at the iteration that escaped, for smooth_iter
+        mag2final = mag2;
+        if mag2 < threshold {
+            mk += 1;
+        } else {
+            break;
+        }
+
+        let ref_mag2 = zr*zr + zi*zi;
+        if detect_glitches && mag2 < ref_mag2 * GLITCH_TOLERANCE {
+            return Pixel::Glitch(mag2 / ref_mag2);
+        }
+    }
+
+    Pixel::Done(if mk >= max_iterations {
+        -1f32
+    } else {
+        smooth_iter((mk + 1) as f32, mag2final as f32, 2f32)
+    })
+}
+
+pub fn perturbation(cfg: &FractalCfg) -> (Vec<f32>, RenderStats) {
+    let width  = cfg.width  as usize;
+    let height = cfg.height as usize;
+    let max_iterations = cfg.max_iterations;
+    let threshold = cfg.bailout.powi(2);
+
+    let (xwidth, ywidth) = calc_width(width, height, cfg.zoom);
+    let xscale = xwidth / (width as f64);
+    let yscale = ywidth / (height as f64);
+    // offset of pixel (x,y) from the center, same mapping as escape_time
+    let delta = |x: usize, y: usize| (
+        (x as f64) * xscale - xwidth / 2.0,
+        (y as f64) * yscale - ywidth / 2.0,
+    );
+
+    // validate has already parsed it once
+    let bits = precision_bits(cfg);
+    let center = parse_center(cfg, bits).unwrap_or_else(|_| {
+        (Fixed::from_f64(cfg.center_r, bits), Fixed::from_f64(cfg.center_i, bits))
+    });
+    let mut stats = RenderStats::default();
+    let mut buf = vec![0f32; width * height];
+    let mut pending: Vec<(usize, usize)> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .collect();
+    let mut offset = (0f64, 0f64);
+
+    for n_ref in 0..cfg.references {
+        let reference = reference_orbit(cfg, &center, offset, threshold);
+        stats.references += 1;
+        // give up on detecting glitches for the last reference, and keep
+        // whatever those pixels come out as
+        let detect_glitches = n_ref + 1 < cfg.references;
+
+        // only the main reference sits in the middle of the image, which is
+        // what the series radius and probes assume
+        let series = if n_ref == 0 && cfg.series_approximation {
+            let (w, h) = (width - 1, height - 1);
+            let probes: Vec<_> = [(0, 0), (w/2, 0), (w, 0), (0, h/2), (w, h/2), (0, h), (w/2, h), (w, h)]
+                .iter()
+                .map(|&(x, y)| delta(x, y))
+                .collect();
+            let radius = probes.iter().map(|d| d.0.hypot(d.1)).fold(0f64, f64::max);
+            let series = series_approximation(&reference, cfg.julia, radius, &probes, threshold);
+            stats.series_skipped = series.skip as u32;
+            series
+        } else {
+            Series::new(1.0)
+        };
+
+        let results: Vec<Pixel> = pending.par_iter()
+            .map(|&(x, y)| {
+                let (dr, di) = delta(x, y);
+                let d = (dr - reference.offset.0, di - reference.offset.1);
+                let dc = if cfg.julia { (0.0, 0.0) } else { d };
+                let dz = if series.skip > 0 { series.eval(d) } else { d };
+                iterate_pixel(&reference, series.skip, dz, dc, max_iterations, threshold, detect_glitches)
+            })
+            .collect();
+
+        let mut glitched = vec![];
+        let mut worst: Option<((usize, usize), f64)> = None;
+        for (&(x, y), pixel) in pending.iter().zip(results.into_iter()) {
+            match pixel {
+                Pixel::Done(v) => buf[(height - y - 1)*width + x] = v,
+                Pixel::Glitch(ratio) => {
+                    glitched.push((x, y));
+                    if worst.map_or(true, |(_, r)| ratio < r) {
+                        worst = Some(((x, y), ratio));
+                    }
+                },
+            }
+        }
+        stats.glitched += glitched.len();
+
+        match worst {
+            // the worst glitch is usually right on top of whatever feature
+            // the old reference missed, so that makes a good new reference
+            Some(((x, y), _)) => {
+                offset = delta(x, y);
+                pending = glitched;
+            },
+            None => break,
+        }
+    }
+    (buf, stats)
+}
@@ -0,0 +1,327 @@
+// rays.rs
+//
+// external rays of the mandelbrot set. the ray at angle t is the curve of c
+// where the argument of phi(c), the map that takes the outside of the set
+// onto the outside of the unit disk, is 2 pi t. far out z_n(c) is about
+// phi(c)^(2^n), so the ray is traced inwards with newton's method on
+// z_n(c) = r e^(2 pi i 2^(n-1) t), for radii r going down from
+// ESCAPE_RADIUS to its square root and then starting over at the next n,
+// each solution the guess for the next
+//
+// rays at rational angles land on the set. the angles that end up repeating
+// under doubling land on the root of a hyperbolic component with the same
+// period, and the ones that get there after some doublings land on a
+// misiurewicz point, so the landing point is found exactly from where the
+// traced ray ends
+use fractal::PixelGrid;
+use nucleus::{find_nucleus, find_misiurewicz};
+use num::Complex;
+use std::f64::consts::PI;
+
+// |z_n| the rays start from, at each n
+const ESCAPE_RADIUS: f64 = 65536.0;
+// points per n. more makes smoother rays that newton's method doesn't jump
+// off of
+const SHARPNESS: u32 = 8;
+// newton steps per point
+const NEWTON_STEPS: usize = 16;
+// steps from the nucleus out to the root of a component
+const ROOT_STEPS: u32 = 16;
+
+// an angle in turns, as a fraction in lowest terms, which doubles exactly
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalAngle {
+    pub num: u64,
+    pub den: u64,
+}
+
+// a fraction like 1/3 or 2/7, or 0. angles of 1 turn or more wrap around
+impl ::std::str::FromStr for ExternalAngle {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ExternalAngle, String> {
+        let mut parts = s.trim().splitn(2, '/');
+        let num = parts.next().unwrap_or("");
+        let den = parts.next().unwrap_or("1");
+        let parse = |x: &str| x.trim().parse::<u64>().map_err(|_| format!("bad external angle {}", s));
+        ExternalAngle::new(parse(num)?, parse(den)?)
+    }
+}
+
+impl ::std::fmt::Display for ExternalAngle {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        if self.num == 0 {
+            write!(f, "0")
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+impl ExternalAngle {
+    // num/den turns. the denominator has to fit doubling the numerator in a
+    // u64
+    pub fn new(num: u64, den: u64) -> Result<ExternalAngle, String> {
+        if den == 0 || den > 1 << 62 {
+            return Err(format!("bad external angle denominator {}", den));
+        }
+        let num = num % den;
+        let d = gcd(num, den);
+        Ok(ExternalAngle { num: num / d, den: den / d })
+    }
+
+    pub fn turns(&self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    // twice the angle, wrapped into one turn
+    pub fn doubled(&self) -> ExternalAngle {
+        ExternalAngle { num: 2 * self.num % self.den, den: self.den }.reduced()
+    }
+
+    fn reduced(&self) -> ExternalAngle {
+        let d = gcd(self.num, self.den);
+        ExternalAngle { num: self.num / d, den: self.den / d }
+    }
+
+    // (preperiod, period) under doubling. with den = 2^k m and m odd, it
+    // takes k doublings to get to an odd denominator, and then the period
+    // is the order of 2 mod m
+    pub fn preperiod_period(&self) -> (u32, u32) {
+        let k = self.den.trailing_zeros();
+        let m = self.den >> k;
+        let mut period = 1;
+        let mut x = 2 % m;
+        while x != 1 % m {
+            x = 2 * x % m;
+            period += 1;
+        }
+        (k, period)
+    }
+}
+
+// a traced ray, from far outside the set in towards where it lands
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ray {
+    pub angle: ExternalAngle,
+    // (re, im), starting at |c| = ESCAPE_RADIUS
+    pub points: Vec<(f64, f64)>,
+    // where the ray ends on the set, or the last point traced if it couldn't
+    // be pinned down
+    pub landing: (f64, f64),
+    // whether landing is the exact root or misiurewicz point
+    pub exact: bool,
+}
+
+// the ray at angle, down to z_depth or until it's within resolution of
+// where it lands, like a pixel of the image it's drawn on. every n costs n
+// iterations per newton step, so depth is what bounds the time
+pub fn trace_ray(angle: ExternalAngle, depth: u32, resolution: f64) -> Ray {
+    let mut c = polar(ESCAPE_RADIUS, angle);
+    let mut points = vec![(c.re, c.im)];
+    let mut a = angle;
+    // where it lands, looked for once the ray slows down to less than
+    // resolution per n. rays that land on roots keep slowing down long
+    // before they get there
+    let mut landing = None;
+    'trace: for n in 1..(depth + 1) {
+        let start = c;
+        for j in 1..(SHARPNESS + 1) {
+            let r = ESCAPE_RADIUS.powf(0.5f64.powf(j as f64 / SHARPNESS as f64));
+            let target = polar(r, a);
+            for _ in 0..NEWTON_STEPS {
+                // z_n and dz_n/dc, with z_1 = c
+                let (mut z, mut dz) = (c, Complex::new(1.0, 0.0));
+                for _ in 1..n {
+                    dz = z * dz * 2.0 + 1.0;
+                    z = z * z + c;
+                }
+                let step = (z - target) / dz;
+                if !step.re.is_finite() || !step.im.is_finite() {
+                    break 'trace;
+                }
+                c = c - step;
+                if step.norm() <= 4.0 * ::std::f64::EPSILON * c.norm() {
+                    break;
+                }
+            }
+            points.push((c.re, c.im));
+        }
+        // out of precision, so the rest would all be the same point
+        let moved = (c - start).norm();
+        if moved <= 4.0 * ::std::f64::EPSILON * c.norm() {
+            break;
+        }
+        // close enough to the set that the rest wouldn't show
+        if landing.is_none() && moved <= resolution {
+            landing = checked_landing(angle, &points);
+            if landing.is_none() {
+                break;
+            }
+        }
+        if let Some(l) = landing {
+            if (c - l).norm() <= resolution {
+                break;
+            }
+        }
+        a = a.doubled();
+    }
+
+    let (landing, exact) = match landing.or_else(|| checked_landing(angle, &points)) {
+        Some(l) => (l, true),
+        None => (c, false),
+    };
+    Ray { angle: angle, points: points, landing: (landing.re, landing.im), exact: exact }
+}
+
+// the landing point from the ray traced so far, but only if it's about where
+// the ray is heading: closer to the end than the last half of the ray is long
+fn checked_landing(angle: ExternalAngle, points: &[(f64, f64)]) -> Option<Complex<f64>> {
+    let end = points[points.len() - 1];
+    let end = Complex::new(end.0, end.1);
+    land(angle, end).and_then(|l| {
+        if (l - end).norm() <= 4.0 * distance(points[points.len() / 2], end) { Some(l) } else { None }
+    })
+}
+
+// r at angle a
+fn polar(r: f64, a: ExternalAngle) -> Complex<f64> {
+    let t = 2.0 * PI * a.turns();
+    Complex::new(r * t.cos(), r * t.sin())
+}
+
+fn distance(p: (f64, f64), c: Complex<f64>) -> f64 {
+    (p.0 - c.re).hypot(p.1 - c.im)
+}
+
+// the landing point near the end of a ray
+fn land(angle: ExternalAngle, end: Complex<f64>) -> Option<Complex<f64>> {
+    let (preperiod, period) = angle.preperiod_period();
+    if preperiod > 0 {
+        // z_0 = 0 is one iteration before c, so the orbit gets to its cycle
+        // one step later than the angle does
+        find_misiurewicz((end.re, end.im), preperiod + 1, period)
+            .map(|m| Complex::new(m.point.0, m.point.1))
+    } else {
+        let nucleus = find_nucleus((end.re, end.im), period)?;
+        if nucleus.period != period {
+            return None;
+        }
+        root(Complex::new(nucleus.center.0, nucleus.center.1), period)
+    }
+}
+
+// the root of the component with this nucleus, where its cycle has
+// multiplier 1. newton's method on z and c together, for the z on the
+// cycle with (f^p)'(z) = m, with m going from 0 at the nucleus out to 1 a
+// step at a time
+fn root(nucleus: Complex<f64>, period: u32) -> Option<Complex<f64>> {
+    let (mut z, mut c) = (Complex::new(0.0, 0.0), nucleus);
+    for k in 1..(ROOT_STEPS + 1) {
+        let m = k as f64 / ROOT_STEPS as f64;
+        // where a satellite bulb touches its parent the two cycles meet, and
+        // newton's method slows down to halving the error every step
+        let steps = if k == ROOT_STEPS { 64 } else { NEWTON_STEPS };
+        for _ in 0..steps {
+            // f^p(z) with its first derivatives in z and c, and the
+            // derivatives of df^p/dz in z and c
+            let (mut w, mut dz, mut dc) = (z, Complex::new(1.0, 0.0), Complex::new(0.0, 0.0));
+            let (mut dzdz, mut dzdc) = (Complex::new(0.0, 0.0), Complex::new(0.0, 0.0));
+            for _ in 0..period {
+                dzdz = (dz * dz + w * dzdz) * 2.0;
+                dzdc = (dc * dz + w * dzdc) * 2.0;
+                dz = w * dz * 2.0;
+                dc = w * dc * 2.0 + 1.0;
+                w = w * w + c;
+            }
+            // w - z = 0 and dz - m = 0
+            let (f1, f2) = (w - z, dz - m);
+            let (a, b, d, e) = (dz - 1.0, dc, dzdz, dzdc);
+            let det = a * e - b * d;
+            let step_z = (f1 * e - b * f2) / det;
+            let step_c = (a * f2 - d * f1) / det;
+            if !step_c.re.is_finite() || !step_c.im.is_finite() || !step_z.re.is_finite() || !step_z.im.is_finite() {
+                break;
+            }
+            z = z - step_z;
+            c = c - step_c;
+            if step_c.norm() <= 4.0 * ::std::f64::EPSILON * c.norm() {
+                break;
+            }
+        }
+    }
+    if c.re.is_finite() && c.im.is_finite() { Some(c) } else { None }
+}
+
+// draws the rays over rgb, an image of grid, pen pixels wide. the pixels
+// under the lines are inverted, which stands out on any colormap
+pub(crate) fn draw_rays(rgb: &mut [u8], width: usize, height: usize, grid: &PixelGrid,
+                        rays: &[Ray], pen: usize) {
+    let mut mask = vec![false; width * height];
+    for ray in rays {
+        // points closer together than a pixel only make more segments, so
+        // they're skipped, except for the last one. the ray stops short of
+        // the set, and goes on to where it lands when that's known
+        let mut positions: Vec<(f64, f64)> = ray.points.iter().map(|p| grid.position(p.0, p.1)).collect();
+        if ray.exact {
+            positions.push(grid.position(ray.landing.0, ray.landing.1));
+        }
+        let mut from = positions[0];
+        for (i, &to) in positions.iter().enumerate().skip(1) {
+            if (to.0 - from.0).hypot(to.1 - from.1) >= 1.0 || i + 1 == positions.len() {
+                draw_line(&mut mask, width, height, from, to, pen);
+                from = to;
+            }
+        }
+    }
+    for (pixel, &m) in rgb.chunks_mut(3).zip(mask.iter()) {
+        if m {
+            for c in pixel.iter_mut() {
+                *c = 255 - *c;
+            }
+        }
+    }
+}
+
+// line from a to b, in (x, row) like PixelGrid::position, with a square pen
+// that's pen pixels on a side. clipped to the image first, the rays start
+// far outside it
+fn draw_line(mask: &mut [bool], width: usize, height: usize, a: (f64, f64), b: (f64, f64), pen: usize) {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    // liang-barsky: the part of a + t (b - a) for t in 0..1 inside the image
+    let (mut t0, mut t1) = (0f64, 1f64);
+    let edges = [(-dx, a.0 + 0.5), (dx, width as f64 - 0.5 - a.0),
+                 (-dy, a.1 + 0.5), (dy, height as f64 - 0.5 - a.1)];
+    for &(p, q) in edges.iter() {
+        if p == 0.0 {
+            if q < 0.0 {
+                return;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    if !(t0 <= t1) {
+        return;
+    }
+    // the pen is centered on the line
+    let offset = (pen as f64 - 1.0) / 2.0;
+    let steps = ((t1 - t0) * dx.abs().max(dy.abs())).ceil().max(1.0) as usize;
+    for i in 0..(steps + 1) {
+        let t = t0 + (t1 - t0) * (i as f64) / (steps as f64);
+        let x0 = (a.0 + t * dx - offset).round();
+        let row0 = (a.1 + t * dy - offset).round();
+        for row in (0..pen).map(|k| row0 + k as f64) {
+            for x in (0..pen).map(|k| x0 + k as f64) {
+                if x >= 0.0 && row >= 0.0 && (x as usize) < width && (row as usize) < height {
+                    mask[(row as usize) * width + x as usize] = true;
+                }
+            }
+        }
+    }
+}
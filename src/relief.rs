@@ -0,0 +1,111 @@
+// relief.rs
+//
+// lights the value field as if it were a height map, for embossed looking
+// images. the heights are log2(value + 1), like normalize, so the smooth
+// iteration count and the distance estimate both come out as slopes that
+// climb towards the set
+
+// angle is the direction the light comes from, in degrees counterclockwise
+// from the right, height scales the slopes, and ambient is how bright the
+// parts facing away from the light are. the slopes are measured across the
+// height of the image, so they look the same at any size
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Relief {
+    pub angle: f64,
+    pub height: f64,
+    pub ambient: f64,
+}
+
+impl Default for Relief {
+    fn default() -> Relief {
+        Relief { angle: 45.0, height: 1.0, ambient: 0.25 }
+    }
+}
+
+// <angle>,<height>,<ambient>, where the ones left off are the defaults, like
+// 135 or 45,2,0.1
+impl ::std::str::FromStr for Relief {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Relief, String> {
+        let args: Vec<&str> = s.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).collect();
+        if args.len() > 3 {
+            return Err(format!("relief takes at most 3 arguments, not {}", args.len()));
+        }
+        let mut numbers = vec![];
+        for x in args {
+            numbers.push(x.parse::<f64>().map_err(|_| format!("bad number {}", x))?);
+        }
+        let d = Relief::default();
+        Ok(Relief {
+            angle: numbers.get(0).cloned().unwrap_or(d.angle),
+            height: numbers.get(1).cloned().unwrap_or(d.height),
+            ambient: numbers.get(2).cloned().unwrap_or(d.ambient),
+        })
+    }
+}
+
+impl Relief {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.ambient >= 0.0 && self.ambient <= 1.0) {
+            return Err(format!("relief ambient must be between 0 and 1, not {}", self.ambient));
+        }
+        if !self.height.is_finite() || !self.angle.is_finite() {
+            return Err(format!("bad relief {:?}", self));
+        }
+        Ok(())
+    }
+
+    // how bright each pixel of the value field comes out, from ambient to 1.
+    // pixels without a height (inside, < 0) stay at 1, and their neighbours
+    // take the slope from the side that has one
+    pub fn shade(&self, value: &[f32], width: usize) -> Vec<f32> {
+        let rows = value.len() / width;
+        let h = |x: usize, row: usize| {
+            let v = value[row*width + x];
+            if v >= 0f32 { Some((v + 1f32).log2() as f64) } else { None }
+        };
+        // the difference across a pixel, from both neighbours when there
+        // are two, one of them at the edges and next to the inside
+        let slope = |before: Option<f64>, here: f64, after: Option<f64>| match (before, after) {
+            (Some(a), Some(b)) => (b - a) / 2.0,
+            (Some(a), None) => here - a,
+            (None, Some(b)) => b - here,
+            (None, None) => 0.0,
+        };
+        // 45 degrees up from the horizon
+        let angle = self.angle.to_radians();
+        let elevation = 45f64.to_radians();
+        let light = (angle.cos() * elevation.cos(), angle.sin() * elevation.cos(), elevation.sin());
+
+        let mut shade = vec![1f32; value.len()];
+        // the image is 1 high
+        let scale = self.height * rows as f64;
+        for row in 0..rows {
+            for x in 0..width {
+                let here = match h(x, row) {
+                    Some(here) => here,
+                    None => continue,
+                };
+                let dx = slope(if x > 0 { h(x - 1, row) } else { None }, here,
+                               if x + 1 < width { h(x + 1, row) } else { None });
+                // rows are stored top to bottom, y goes up
+                let dy = slope(if row + 1 < rows { h(x, row + 1) } else { None }, here,
+                               if row > 0 { h(x, row - 1) } else { None });
+                // normal of the surface z = height * h(x, y)
+                let (nx, ny, nz) = (-scale * dx, -scale * dy, 1.0);
+                let diffuse = (nx * light.0 + ny * light.1 + nz * light.2) / (nx*nx + ny*ny + nz*nz).sqrt();
+                shade[row*width + x] = (self.ambient + (1.0 - self.ambient) * diffuse.max(0.0)) as f32;
+            }
+        }
+        shade
+    }
+}
+
+// multiplies each pixel of rgb by its shade
+pub fn apply_shade(rgb: &mut [u8], shade: &[f32]) {
+    for (pixel, &x) in rgb.chunks_mut(3).zip(shade.iter()) {
+        for c in pixel.iter_mut() {
+            *c = (*c as f32 * x) as u8;
+        }
+    }
+}
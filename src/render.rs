@@ -0,0 +1,175 @@
+// render.rs
+//
+// everything a render can say about a pixel. the value is always there, the
+// other channels only when they're asked for, and only from renderers that
+// know them (the escape time kernel). the rest stay None
+use std::str::FromStr;
+
+// which channels to fill in besides the value
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Channels {
+    pub final_z: bool,
+    pub angle: bool,
+    pub derivative: bool,
+    pub min_modulus: bool,
+    pub period: bool,
+    pub multiplier: bool,
+}
+
+impl Channels {
+    pub fn all() -> Channels {
+        Channels { final_z: true, angle: true, derivative: true, min_modulus: true, period: true, multiplier: true }
+    }
+
+    // both of them
+    pub fn union(&self, other: Channels) -> Channels {
+        Channels {
+            final_z: self.final_z || other.final_z,
+            angle: self.angle || other.angle,
+            derivative: self.derivative || other.derivative,
+            min_modulus: self.min_modulus || other.min_modulus,
+            period: self.period || other.period,
+            multiplier: self.multiplier || other.multiplier,
+        }
+    }
+}
+
+// final-z,angle,derivative,min-modulus,period,multiplier, any number of them
+impl FromStr for Channels {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Channels, String> {
+        let mut channels = Channels::default();
+        for name in s.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            match name {
+                "final-z" => channels.final_z = true,
+                "angle" => channels.angle = true,
+                "derivative" => channels.derivative = true,
+                "min-modulus" => channels.min_modulus = true,
+                "period" => channels.period = true,
+                "multiplier" => channels.multiplier = true,
+                _ => return Err(format!("unknown channel {}", name)),
+            }
+        }
+        Ok(channels)
+    }
+}
+
+// one entry per pixel in every buffer, rows top to bottom. complex numbers
+// are (re, im) pairs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Render {
+    // what mandelbrot() returns: smooth iterations with -1 inside, or
+    // whatever the formula or coloring gives instead
+    pub value: Vec<f32>,
+    // z at the iteration that escaped, or after the last one inside
+    pub final_z: Option<Vec<(f32, f32)>>,
+    // arg of z at the iteration that escaped, in [-pi, pi]. NaN for points
+    // that didn't, so unlike final_z it doesn't need the orbits inside
+    pub angle: Option<Vec<f32>>,
+    // dz/dc (dz/dz0 for julia sets) at the same iteration as final_z
+    pub derivative: Option<Vec<(f32, f32)>>,
+    // smallest |z| along the orbit
+    pub min_modulus: Option<Vec<f32>>,
+    // period of the cycle the orbit settles into, for points inside. 0 for
+    // points outside, and inside where no cycle was found
+    pub period: Option<Vec<u32>>,
+    // derivative of the cycle with respect to z, ie. of `period` iterations
+    // starting on the cycle, for points inside. |multiplier| < 1 because
+    // the cycle attracts, and it's 0 at the center of its component. only
+    // known for z^2 + c, (0, 0) everywhere else
+    pub multiplier: Option<Vec<(f32, f32)>>,
+}
+
+impl Render {
+    pub fn new(n_pixels: usize, channels: Channels) -> Render {
+        fn buffer<T: Clone + Default>(wanted: bool, n_pixels: usize) -> Option<Vec<T>> {
+            if wanted { Some(vec![T::default(); n_pixels]) } else { None }
+        }
+        Render {
+            value: vec![0f32; n_pixels],
+            final_z: buffer(channels.final_z, n_pixels),
+            angle: buffer(channels.angle, n_pixels),
+            derivative: buffer(channels.derivative, n_pixels),
+            min_modulus: buffer(channels.min_modulus, n_pixels),
+            period: buffer(channels.period, n_pixels),
+            multiplier: buffer(channels.multiplier, n_pixels),
+        }
+    }
+
+    // for the renderers that only have a value
+    pub fn from_value(value: Vec<f32>) -> Render {
+        Render { value: value, .. Render::new(0, Channels::default()) }
+    }
+
+    // the channels that are filled in
+    pub fn channels(&self) -> Channels {
+        Channels {
+            final_z: self.final_z.is_some(),
+            angle: self.angle.is_some(),
+            derivative: self.derivative.is_some(),
+            min_modulus: self.min_modulus.is_some(),
+            period: self.period.is_some(),
+            multiplier: self.multiplier.is_some(),
+        }
+    }
+
+    // every row on its own, to render in parallel
+    pub(crate) fn rows<'a>(&'a mut self, width: usize) -> Vec<RenderRows<'a>> {
+        fn split<T>(buf: &mut Option<Vec<T>>, width: usize, n_rows: usize) -> Vec<Option<&mut [T]>> {
+            match *buf {
+                Some(ref mut buf) => buf.chunks_mut(width).map(Some).collect(),
+                None => (0..n_rows).map(|_| None).collect(),
+            }
+        }
+        let n_rows = self.value.len() / width;
+        let final_z = split(&mut self.final_z, width, n_rows);
+        let angle = split(&mut self.angle, width, n_rows);
+        let derivative = split(&mut self.derivative, width, n_rows);
+        let min_modulus = split(&mut self.min_modulus, width, n_rows);
+        let period = split(&mut self.period, width, n_rows);
+        let multiplier = split(&mut self.multiplier, width, n_rows);
+        self.value.chunks_mut(width)
+            .zip(final_z.into_iter())
+            .zip(angle.into_iter())
+            .zip(derivative.into_iter())
+            .zip(min_modulus.into_iter())
+            .zip(period.into_iter())
+            .zip(multiplier.into_iter())
+            .map(|((((((value, final_z), angle), derivative), min_modulus), period), multiplier)| RenderRows {
+                value: value,
+                final_z: final_z,
+                angle: angle,
+                derivative: derivative,
+                min_modulus: min_modulus,
+                period: period,
+                multiplier: multiplier,
+            })
+            .collect()
+    }
+}
+
+// whole rows of a Render, what Backend::render writes to. the channels that
+// are None are skipped
+pub struct RenderRows<'a> {
+    pub value: &'a mut [f32],
+    pub final_z: Option<&'a mut [(f32, f32)]>,
+    pub angle: Option<&'a mut [f32]>,
+    pub derivative: Option<&'a mut [(f32, f32)]>,
+    pub min_modulus: Option<&'a mut [f32]>,
+    pub period: Option<&'a mut [u32]>,
+    pub multiplier: Option<&'a mut [(f32, f32)]>,
+}
+
+impl<'a> RenderRows<'a> {
+    // just the value
+    pub fn new(value: &'a mut [f32]) -> RenderRows<'a> {
+        RenderRows { value: value, final_z: None, angle: None, derivative: None, min_modulus: None,
+                     period: None, multiplier: None }
+    }
+
+    // the channels besides the value that need the orbits of points inside
+    // iterated all the way, instead of stopping once they're known to be in
+    pub(crate) fn need_whole_orbit(&self) -> bool {
+        self.final_z.is_some() || self.derivative.is_some() || self.min_modulus.is_some()
+    }
+}
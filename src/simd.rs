@@ -0,0 +1,450 @@
+// simd.rs
+use std::ops::{Add, Sub, Mul};
+use num::{Float, Zero};
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+// widest vector we have, used to size scratch arrays for load/store
+pub const MAX_LANES: usize = 8;
+
+// A pack of LANES floats that the kernels in fractal.rs are written against.
+// Every method has to be #[inline(always)]: the intrinsics only get compiled
+// with the right instruction set once they are inlined into the
+// #[target_feature] entry points in backend.rs
+pub trait Vector: Copy + Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> {
+    type Elem: Float;
+    type Mask: Mask;
+    const LANES: usize;
+
+    fn splat(x: Self::Elem) -> Self;
+    // xs must hold at least LANES elements
+    fn load(xs: &[Self::Elem]) -> Self;
+    fn store(self, out: &mut [Self::Elem]);
+    fn lt(self, other: Self) -> Self::Mask;
+    fn abs(self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn sqrt(self) -> Self;
+    // lanes of a where mask is set, lanes of b elsewhere
+    fn select(mask: Self::Mask, a: Self, b: Self) -> Self;
+
+    // self * b + c, fused where the backend has FMA
+    #[inline(always)]
+    fn mul_add(self, b: Self, c: Self) -> Self {
+        self * b + c
+    }
+
+    // applies f to each pair of lanes, for the things that have no intrinsics
+    #[inline(always)]
+    fn map2<F>(a: Self, b: Self, f: F) -> (Self, Self)
+        where F: Fn(Self::Elem, Self::Elem) -> (Self::Elem, Self::Elem) {
+        let mut xs = [Self::Elem::zero(); MAX_LANES];
+        let mut ys = [Self::Elem::zero(); MAX_LANES];
+        a.store(&mut xs);
+        b.store(&mut ys);
+        for i in 0..Self::LANES {
+            let (x, y) = f(xs[i], ys[i]);
+            xs[i] = x;
+            ys[i] = y;
+        }
+        (Self::load(&xs), Self::load(&ys))
+    }
+}
+
+pub trait Mask: Copy {
+    // true if any lane is set
+    fn any(self) -> bool;
+}
+
+impl Mask for bool {
+    #[inline(always)]
+    fn any(self) -> bool { self }
+}
+
+impl Vector for f32 {
+    type Elem = f32;
+    type Mask = bool;
+    const LANES: usize = 1;
+
+    #[inline(always)]
+    fn splat(x: f32) -> f32 { x }
+    #[inline(always)]
+    fn load(xs: &[f32]) -> f32 { xs[0] }
+    #[inline(always)]
+    fn store(self, out: &mut [f32]) { out[0] = self; }
+    #[inline(always)]
+    fn lt(self, other: f32) -> bool { self < other }
+    #[inline(always)]
+    fn abs(self) -> f32 { Float::abs(self) }
+    #[inline(always)]
+    fn min(self, other: f32) -> f32 { Float::min(self, other) }
+    #[inline(always)]
+    fn sqrt(self) -> f32 { Float::sqrt(self) }
+    #[inline(always)]
+    fn select(mask: bool, a: f32, b: f32) -> f32 { if mask {a} else {b} }
+}
+
+impl Vector for f64 {
+    type Elem = f64;
+    type Mask = bool;
+    const LANES: usize = 1;
+
+    #[inline(always)]
+    fn splat(x: f64) -> f64 { x }
+    #[inline(always)]
+    fn load(xs: &[f64]) -> f64 { xs[0] }
+    #[inline(always)]
+    fn store(self, out: &mut [f64]) { out[0] = self; }
+    #[inline(always)]
+    fn lt(self, other: f64) -> bool { self < other }
+    #[inline(always)]
+    fn abs(self) -> f64 { Float::abs(self) }
+    #[inline(always)]
+    fn min(self, other: f64) -> f64 { Float::min(self, other) }
+    #[inline(always)]
+    fn sqrt(self) -> f64 { Float::sqrt(self) }
+    #[inline(always)]
+    fn select(mask: bool, a: f64, b: f64) -> f64 { if mask {a} else {b} }
+}
+
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub use self::x86::*;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    use super::*;
+
+    // all of the unsafe blocks below are only reached through the
+    // #[target_feature] functions in backend.rs, which check the cpu first
+
+    #[derive(Clone, Copy)]
+    pub struct F32x4(__m128);
+    #[derive(Clone, Copy)]
+    pub struct M32x4(__m128);
+
+    impl Mask for M32x4 {
+        #[inline(always)]
+        fn any(self) -> bool { unsafe { _mm_movemask_ps(self.0) != 0 } }
+    }
+
+    impl Add for F32x4 {
+        type Output = F32x4;
+        #[inline(always)]
+        fn add(self, o: F32x4) -> F32x4 { unsafe { F32x4(_mm_add_ps(self.0, o.0)) } }
+    }
+    impl Sub for F32x4 {
+        type Output = F32x4;
+        #[inline(always)]
+        fn sub(self, o: F32x4) -> F32x4 { unsafe { F32x4(_mm_sub_ps(self.0, o.0)) } }
+    }
+    impl Mul for F32x4 {
+        type Output = F32x4;
+        #[inline(always)]
+        fn mul(self, o: F32x4) -> F32x4 { unsafe { F32x4(_mm_mul_ps(self.0, o.0)) } }
+    }
+
+    impl Vector for F32x4 {
+        type Elem = f32;
+        type Mask = M32x4;
+        const LANES: usize = 4;
+
+        #[inline(always)]
+        fn splat(x: f32) -> F32x4 { unsafe { F32x4(_mm_set1_ps(x)) } }
+        #[inline(always)]
+        fn load(xs: &[f32]) -> F32x4 {
+            assert!(xs.len() >= 4);
+            unsafe { F32x4(_mm_loadu_ps(xs.as_ptr())) }
+        }
+        #[inline(always)]
+        fn store(self, out: &mut [f32]) {
+            assert!(out.len() >= 4);
+            unsafe { _mm_storeu_ps(out.as_mut_ptr(), self.0) }
+        }
+        #[inline(always)]
+        fn lt(self, o: F32x4) -> M32x4 { unsafe { M32x4(_mm_cmplt_ps(self.0, o.0)) } }
+        // clears the sign bit
+        #[inline(always)]
+        fn abs(self) -> F32x4 { unsafe { F32x4(_mm_andnot_ps(_mm_set1_ps(-0.0), self.0)) } }
+        #[inline(always)]
+        fn min(self, o: F32x4) -> F32x4 { unsafe { F32x4(_mm_min_ps(self.0, o.0)) } }
+        #[inline(always)]
+        fn sqrt(self) -> F32x4 { unsafe { F32x4(_mm_sqrt_ps(self.0)) } }
+        #[inline(always)]
+        fn select(m: M32x4, a: F32x4, b: F32x4) -> F32x4 {
+            // no blendv before sse4.1
+            unsafe { F32x4(_mm_or_ps(_mm_and_ps(m.0, a.0), _mm_andnot_ps(m.0, b.0))) }
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    pub struct F32x8(__m256);
+    #[derive(Clone, Copy)]
+    pub struct M32x8(__m256);
+
+    impl Mask for M32x8 {
+        #[inline(always)]
+        fn any(self) -> bool { unsafe { _mm256_testz_ps(self.0, self.0) == 0 } }
+    }
+
+    impl Add for F32x8 {
+        type Output = F32x8;
+        #[inline(always)]
+        fn add(self, o: F32x8) -> F32x8 { unsafe { F32x8(_mm256_add_ps(self.0, o.0)) } }
+    }
+    impl Sub for F32x8 {
+        type Output = F32x8;
+        #[inline(always)]
+        fn sub(self, o: F32x8) -> F32x8 { unsafe { F32x8(_mm256_sub_ps(self.0, o.0)) } }
+    }
+    impl Mul for F32x8 {
+        type Output = F32x8;
+        #[inline(always)]
+        fn mul(self, o: F32x8) -> F32x8 { unsafe { F32x8(_mm256_mul_ps(self.0, o.0)) } }
+    }
+
+    impl Vector for F32x8 {
+        type Elem = f32;
+        type Mask = M32x8;
+        const LANES: usize = 8;
+
+        #[inline(always)]
+        fn splat(x: f32) -> F32x8 { unsafe { F32x8(_mm256_set1_ps(x)) } }
+        #[inline(always)]
+        fn load(xs: &[f32]) -> F32x8 {
+            assert!(xs.len() >= 8);
+            unsafe { F32x8(_mm256_loadu_ps(xs.as_ptr())) }
+        }
+        #[inline(always)]
+        fn store(self, out: &mut [f32]) {
+            assert!(out.len() >= 8);
+            unsafe { _mm256_storeu_ps(out.as_mut_ptr(), self.0) }
+        }
+        #[inline(always)]
+        fn lt(self, o: F32x8) -> M32x8 { unsafe { M32x8(_mm256_cmp_ps(self.0, o.0, _CMP_LT_OS)) } }
+        #[inline(always)]
+        fn abs(self) -> F32x8 { unsafe { F32x8(_mm256_andnot_ps(_mm256_set1_ps(-0.0), self.0)) } }
+        #[inline(always)]
+        fn min(self, o: F32x8) -> F32x8 { unsafe { F32x8(_mm256_min_ps(self.0, o.0)) } }
+        #[inline(always)]
+        fn sqrt(self) -> F32x8 { unsafe { F32x8(_mm256_sqrt_ps(self.0)) } }
+        #[inline(always)]
+        fn select(m: M32x8, a: F32x8, b: F32x8) -> F32x8 {
+            unsafe { F32x8(_mm256_blendv_ps(b.0, a.0, m.0)) }
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    pub struct F64x2(__m128d);
+    #[derive(Clone, Copy)]
+    pub struct M64x2(__m128d);
+
+    impl Mask for M64x2 {
+        #[inline(always)]
+        fn any(self) -> bool { unsafe { _mm_movemask_pd(self.0) != 0 } }
+    }
+
+    impl Add for F64x2 {
+        type Output = F64x2;
+        #[inline(always)]
+        fn add(self, o: F64x2) -> F64x2 { unsafe { F64x2(_mm_add_pd(self.0, o.0)) } }
+    }
+    impl Sub for F64x2 {
+        type Output = F64x2;
+        #[inline(always)]
+        fn sub(self, o: F64x2) -> F64x2 { unsafe { F64x2(_mm_sub_pd(self.0, o.0)) } }
+    }
+    impl Mul for F64x2 {
+        type Output = F64x2;
+        #[inline(always)]
+        fn mul(self, o: F64x2) -> F64x2 { unsafe { F64x2(_mm_mul_pd(self.0, o.0)) } }
+    }
+
+    impl Vector for F64x2 {
+        type Elem = f64;
+        type Mask = M64x2;
+        const LANES: usize = 2;
+
+        #[inline(always)]
+        fn splat(x: f64) -> F64x2 { unsafe { F64x2(_mm_set1_pd(x)) } }
+        #[inline(always)]
+        fn load(xs: &[f64]) -> F64x2 {
+            assert!(xs.len() >= 2);
+            unsafe { F64x2(_mm_loadu_pd(xs.as_ptr())) }
+        }
+        #[inline(always)]
+        fn store(self, out: &mut [f64]) {
+            assert!(out.len() >= 2);
+            unsafe { _mm_storeu_pd(out.as_mut_ptr(), self.0) }
+        }
+        #[inline(always)]
+        fn lt(self, o: F64x2) -> M64x2 { unsafe { M64x2(_mm_cmplt_pd(self.0, o.0)) } }
+        #[inline(always)]
+        fn abs(self) -> F64x2 { unsafe { F64x2(_mm_andnot_pd(_mm_set1_pd(-0.0), self.0)) } }
+        #[inline(always)]
+        fn min(self, o: F64x2) -> F64x2 { unsafe { F64x2(_mm_min_pd(self.0, o.0)) } }
+        #[inline(always)]
+        fn sqrt(self) -> F64x2 { unsafe { F64x2(_mm_sqrt_pd(self.0)) } }
+        #[inline(always)]
+        fn select(m: M64x2, a: F64x2, b: F64x2) -> F64x2 {
+            unsafe { F64x2(_mm_or_pd(_mm_and_pd(m.0, a.0), _mm_andnot_pd(m.0, b.0))) }
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    pub struct F64x4(__m256d);
+    #[derive(Clone, Copy)]
+    pub struct M64x4(__m256d);
+
+    impl Mask for M64x4 {
+        #[inline(always)]
+        fn any(self) -> bool { unsafe { _mm256_testz_pd(self.0, self.0) == 0 } }
+    }
+
+    impl Add for F64x4 {
+        type Output = F64x4;
+        #[inline(always)]
+        fn add(self, o: F64x4) -> F64x4 { unsafe { F64x4(_mm256_add_pd(self.0, o.0)) } }
+    }
+    impl Sub for F64x4 {
+        type Output = F64x4;
+        #[inline(always)]
+        fn sub(self, o: F64x4) -> F64x4 { unsafe { F64x4(_mm256_sub_pd(self.0, o.0)) } }
+    }
+    impl Mul for F64x4 {
+        type Output = F64x4;
+        #[inline(always)]
+        fn mul(self, o: F64x4) -> F64x4 { unsafe { F64x4(_mm256_mul_pd(self.0, o.0)) } }
+    }
+
+    impl Vector for F64x4 {
+        type Elem = f64;
+        type Mask = M64x4;
+        const LANES: usize = 4;
+
+        #[inline(always)]
+        fn splat(x: f64) -> F64x4 { unsafe { F64x4(_mm256_set1_pd(x)) } }
+        #[inline(always)]
+        fn load(xs: &[f64]) -> F64x4 {
+            assert!(xs.len() >= 4);
+            unsafe { F64x4(_mm256_loadu_pd(xs.as_ptr())) }
+        }
+        #[inline(always)]
+        fn store(self, out: &mut [f64]) {
+            assert!(out.len() >= 4);
+            unsafe { _mm256_storeu_pd(out.as_mut_ptr(), self.0) }
+        }
+        #[inline(always)]
+        fn lt(self, o: F64x4) -> M64x4 { unsafe { M64x4(_mm256_cmp_pd(self.0, o.0, _CMP_LT_OS)) } }
+        #[inline(always)]
+        fn abs(self) -> F64x4 { unsafe { F64x4(_mm256_andnot_pd(_mm256_set1_pd(-0.0), self.0)) } }
+        #[inline(always)]
+        fn min(self, o: F64x4) -> F64x4 { unsafe { F64x4(_mm256_min_pd(self.0, o.0)) } }
+        #[inline(always)]
+        fn sqrt(self) -> F64x4 { unsafe { F64x4(_mm256_sqrt_pd(self.0)) } }
+        #[inline(always)]
+        fn select(m: M64x4, a: F64x4, b: F64x4) -> F64x4 {
+            unsafe { F64x4(_mm256_blendv_pd(b.0, a.0, m.0)) }
+        }
+    }
+
+    // same as F32x8, but mul_add is a single fused instruction
+    #[derive(Clone, Copy)]
+    pub struct F32x8Fma(F32x8);
+
+    impl Add for F32x8Fma {
+        type Output = F32x8Fma;
+        #[inline(always)]
+        fn add(self, o: F32x8Fma) -> F32x8Fma { F32x8Fma(self.0 + o.0) }
+    }
+    impl Sub for F32x8Fma {
+        type Output = F32x8Fma;
+        #[inline(always)]
+        fn sub(self, o: F32x8Fma) -> F32x8Fma { F32x8Fma(self.0 - o.0) }
+    }
+    impl Mul for F32x8Fma {
+        type Output = F32x8Fma;
+        #[inline(always)]
+        fn mul(self, o: F32x8Fma) -> F32x8Fma { F32x8Fma(self.0 * o.0) }
+    }
+
+    impl Vector for F32x8Fma {
+        type Elem = f32;
+        type Mask = M32x8;
+        const LANES: usize = 8;
+
+        #[inline(always)]
+        fn splat(x: f32) -> F32x8Fma { F32x8Fma(F32x8::splat(x)) }
+        #[inline(always)]
+        fn load(xs: &[f32]) -> F32x8Fma { F32x8Fma(F32x8::load(xs)) }
+        #[inline(always)]
+        fn store(self, out: &mut [f32]) { self.0.store(out) }
+        #[inline(always)]
+        fn lt(self, o: F32x8Fma) -> M32x8 { self.0.lt(o.0) }
+        #[inline(always)]
+        fn abs(self) -> F32x8Fma { F32x8Fma(self.0.abs()) }
+        #[inline(always)]
+        fn min(self, o: F32x8Fma) -> F32x8Fma { F32x8Fma(self.0.min(o.0)) }
+        #[inline(always)]
+        fn sqrt(self) -> F32x8Fma { F32x8Fma(self.0.sqrt()) }
+        #[inline(always)]
+        fn select(m: M32x8, a: F32x8Fma, b: F32x8Fma) -> F32x8Fma {
+            F32x8Fma(F32x8::select(m, a.0, b.0))
+        }
+        #[inline(always)]
+        fn mul_add(self, b: F32x8Fma, c: F32x8Fma) -> F32x8Fma {
+            unsafe { F32x8Fma(F32x8(_mm256_fmadd_ps((self.0).0, (b.0).0, (c.0).0))) }
+        }
+    }
+
+    // same as F64x4, but mul_add is a single fused instruction
+    #[derive(Clone, Copy)]
+    pub struct F64x4Fma(F64x4);
+
+    impl Add for F64x4Fma {
+        type Output = F64x4Fma;
+        #[inline(always)]
+        fn add(self, o: F64x4Fma) -> F64x4Fma { F64x4Fma(self.0 + o.0) }
+    }
+    impl Sub for F64x4Fma {
+        type Output = F64x4Fma;
+        #[inline(always)]
+        fn sub(self, o: F64x4Fma) -> F64x4Fma { F64x4Fma(self.0 - o.0) }
+    }
+    impl Mul for F64x4Fma {
+        type Output = F64x4Fma;
+        #[inline(always)]
+        fn mul(self, o: F64x4Fma) -> F64x4Fma { F64x4Fma(self.0 * o.0) }
+    }
+
+    impl Vector for F64x4Fma {
+        type Elem = f64;
+        type Mask = M64x4;
+        const LANES: usize = 4;
+
+        #[inline(always)]
+        fn splat(x: f64) -> F64x4Fma { F64x4Fma(F64x4::splat(x)) }
+        #[inline(always)]
+        fn load(xs: &[f64]) -> F64x4Fma { F64x4Fma(F64x4::load(xs)) }
+        #[inline(always)]
+        fn store(self, out: &mut [f64]) { self.0.store(out) }
+        #[inline(always)]
+        fn lt(self, o: F64x4Fma) -> M64x4 { self.0.lt(o.0) }
+        #[inline(always)]
+        fn abs(self) -> F64x4Fma { F64x4Fma(self.0.abs()) }
+        #[inline(always)]
+        fn min(self, o: F64x4Fma) -> F64x4Fma { F64x4Fma(self.0.min(o.0)) }
+        #[inline(always)]
+        fn sqrt(self) -> F64x4Fma { F64x4Fma(self.0.sqrt()) }
+        #[inline(always)]
+        fn select(m: M64x4, a: F64x4Fma, b: F64x4Fma) -> F64x4Fma {
+            F64x4Fma(F64x4::select(m, a.0, b.0))
+        }
+        #[inline(always)]
+        fn mul_add(self, b: F64x4Fma, c: F64x4Fma) -> F64x4Fma {
+            unsafe { F64x4Fma(F64x4(_mm256_fmadd_pd((self.0).0, (b.0).0, (c.0).0))) }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// trap.rs
+//
+// orbit traps: instead of how long the orbit took to escape, color by how
+// close it ever got to some shape
+use simd::Vector;
+use newton::parse_complex;
+use num::cast;
+
+// points are (re, im), angles in degrees
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Trap {
+    Point(f64, f64),
+    // center and radius
+    Circle(f64, f64, f64),
+    // a horizontal and a vertical line through the point
+    Cross(f64, f64),
+    // through the point, at the angle
+    Line(f64, f64, f64),
+}
+
+// point:<c>, circle:<c>,<radius>, cross:<c>, line:<c>,<angle>
+// like circle:0.5i,0.25 or line:0,45
+impl ::std::str::FromStr for Trap {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Trap, String> {
+        let mut parts = s.splitn(2, ':');
+        let name = parts.next().unwrap();
+        let args: Vec<&str> = parts.next().map_or(vec![], |x| x.split(',').collect());
+        let n_args = |n: usize| if args.len() == n {
+            Ok(())
+        } else {
+            Err(format!("trap {} takes {} arguments, not {}", name, n, args.len()))
+        };
+        let float = |x: &str| x.trim().parse::<f64>().map_err(|_| format!("bad number {}", x));
+        match name {
+            "point" => {
+                n_args(1)?;
+                let (x, y) = parse_complex(args[0])?;
+                Ok(Trap::Point(x, y))
+            },
+            "circle" => {
+                n_args(2)?;
+                let (x, y) = parse_complex(args[0])?;
+                Ok(Trap::Circle(x, y, float(args[1])?))
+            },
+            "cross" => {
+                n_args(1)?;
+                let (x, y) = parse_complex(args[0])?;
+                Ok(Trap::Cross(x, y))
+            },
+            "line" => {
+                n_args(2)?;
+                let (x, y) = parse_complex(args[0])?;
+                Ok(Trap::Line(x, y, float(args[1])?))
+            },
+            _ => Err(format!("unknown trap {}", s)),
+        }
+    }
+}
+
+// a trap with its constants splatted, for the kernel
+pub(crate) struct VectorTrap<V> {
+    trap: Trap,
+    x: V,
+    y: V,
+    // radius for circles, sin and cos of the angle for lines
+    a: V,
+    b: V,
+}
+
+impl<V: Vector> VectorTrap<V> {
+    pub fn new(trap: Trap) -> VectorTrap<V> {
+        let splat = |x: f64| V::splat(cast(x).unwrap());
+        let (x, y, a, b) = match trap {
+            Trap::Point(x, y) | Trap::Cross(x, y) => (x, y, 0.0, 0.0),
+            Trap::Circle(x, y, r) => (x, y, r, 0.0),
+            Trap::Line(x, y, angle) => {
+                let angle = angle.to_radians();
+                (x, y, angle.sin(), angle.cos())
+            },
+        };
+        VectorTrap { trap: trap, x: splat(x), y: splat(y), a: splat(a), b: splat(b) }
+    }
+
+    // distance from z to the trap
+    #[inline(always)]
+    pub fn distance(&self, zr: V, zi: V) -> V {
+        let dx = zr - self.x;
+        let dy = zi - self.y;
+        match self.trap {
+            Trap::Point(..) => dx.mul_add(dx, dy * dy).sqrt(),
+            Trap::Circle(..) => (dx.mul_add(dx, dy * dy).sqrt() - self.a).abs(),
+            Trap::Cross(..) => dx.abs().min(dy.abs()),
+            // the component of (dx, dy) across the line
+            Trap::Line(..) => (dx * self.a - dy * self.b).abs(),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+extern crate fractals;
+use fractals::*;
+
+#[test]
+fn test_parse_average() {
+    assert_eq!("stripe".parse::<Average>(), Ok(Average::Stripe(5.0)));
+    assert_eq!("stripe:3".parse::<Average>(), Ok(Average::Stripe(3.0)));
+    assert_eq!("triangle".parse::<Average>(), Ok(Average::Triangle));
+    assert!("stripe:x".parse::<Average>().is_err());
+    assert!("triangle:2".parse::<Average>().is_err());
+    assert!("curvature".parse::<Average>().is_err());
+}
+
+#[test]
+fn test_averages() {
+    for &average in [Average::Stripe(5.0), Average::Triangle].iter() {
+        for &formula in [Formula::Mandelbrot, Formula::Multibrot(3.0), Formula::BurningShip].iter() {
+            let cfg = FractalCfg {
+                width: 61, height: 37, max_iterations: 200,
+                formula: formula,
+                average: Some(average),
+                .. FractalCfg::default()
+            };
+            let plain = mandelbrot_with_backend(&FractalCfg { average: None, .. cfg.clone() }, Backend::Scalar);
+            let scalar = mandelbrot_with_backend(&cfg, Backend::Scalar);
+            // the inside stays the inside, and the outside is averaged
+            // between 0 and 1
+            for (a, b) in plain.iter().zip(scalar.iter()) {
+                assert_eq!(*a < 0f32, *b < 0f32);
+                assert!(*b < 0f32 || (*b >= 0f32 && *b <= 1f32), "{:?} {:?}: {}", average, formula, b);
+            }
+            for backend in Backend::available() {
+                let buf = mandelbrot_with_backend(&cfg, backend);
+                // only fma rounds differently
+                if backend != Backend::Avx2Fma {
+                    assert!(buf == scalar, "{:?} {:?} {:?} differs from scalar", average, formula, backend);
+                    continue;
+                }
+                let n_diff = scalar.iter().zip(buf.iter())
+                    .filter(|&(a, b)| (a - b).abs() > 1e-2)
+                    .count();
+                assert!(n_diff * 50 < buf.len(), "{:?} {:?} differs on {} pixels", average, backend, n_diff);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_stripe_matches_orbit() {
+    let cfg = FractalCfg {
+        width: 41, height: 41, max_iterations: 100,
+        average: Some(Average::Stripe(3.0)),
+        precision: Precision::Double,
+        .. FractalCfg::default()
+    };
+    let buf = mandelbrot(&cfg);
+    let (w, h) = (cfg.width as usize, cfg.height as usize);
+    let threshold = cfg.bailout * cfg.bailout;
+    for &(x, row) in [(0, 0), (5, 20), (10, 30), (33, 7), (25, 35)].iter() {
+        let (cr, ci) = (-2.0 + 4.0 * x as f64 / w as f64, -2.0 + 4.0 * (h - row - 1) as f64 / h as f64);
+        let (mut zr, mut zi) = (cr, ci);
+        let mut terms = vec![];
+        loop {
+            let r = zr * zr - zi * zi + cr;
+            zi = 2.0 * zr * zi + ci;
+            zr = r;
+            terms.push(0.5 * (3.0 * zi.atan2(zr)).sin() + 0.5);
+            if zr * zr + zi * zi >= threshold {
+                break;
+            }
+        }
+        // between the average without the last term and with it, by how far
+        // past the bailout z got
+        let n = terms.len() as f64;
+        let with = terms.iter().sum::<f64>() / n;
+        let without = terms[..terms.len() - 1].iter().sum::<f64>() / (n - 1.0);
+        let d = 1.0 - (zr.hypot(zi).ln() / cfg.bailout.ln()).log2();
+        let expected = d * with + (1.0 - d) * without;
+        let got = buf[row*w + x] as f64;
+        assert!((got - expected).abs() < 1e-5, "pixel {},{}: {} vs {}", x, row, got, expected);
+    }
+}
+
+#[test]
+fn test_average_validation() {
+    let cfg = FractalCfg { average: Some(Average::Triangle), .. FractalCfg::default() };
+    assert!(cfg.validate().is_ok());
+    assert!(FractalCfg { formula: Formula::Phoenix(-0.5, 0.0), .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { formula: Formula::Newton, .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { distance_estimate: true, .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { trap: Some(Trap::Point(0.0, 0.0)), .. cfg.clone() }.validate().is_err());
+    let cfg = FractalCfg { average: Some(Average::Stripe(1.0)), formula: Formula::Phoenix(-0.5, 0.0), .. cfg };
+    assert!(cfg.validate().is_ok());
+    // the perturbation renderer only does escape times
+    assert_eq!(FractalCfg { precision: Precision::Perturbation, .. cfg }.resolve_precision(), Precision::Double);
+}
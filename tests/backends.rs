@@ -0,0 +1,161 @@
+extern crate fractals;
+use fractals::*;
+
+fn test_cfgs() -> Vec<FractalCfg> {
+    vec![
+        FractalCfg { width: 64, height: 45, .. FractalCfg::default() },
+        FractalCfg {
+            width: 48, height: 64,
+            center_r: -0.743643, center_i: 0.131825,
+            zoom: 200.0,
+            max_iterations: 1000,
+            .. FractalCfg::default()
+        },
+        FractalCfg {
+            width: 64, height: 64,
+            julia: true,
+            cr: 0.285, ci: 0.01,
+            .. FractalCfg::default()
+        },
+        FractalCfg {
+            width: 61, height: 37,
+            center_r: -0.5,
+            .. FractalCfg::default()
+        },
+        FractalCfg {
+            width: 64, height: 45,
+            precision: Precision::Double,
+            .. FractalCfg::default()
+        },
+        FractalCfg {
+            width: 48, height: 64,
+            center_r: -0.743643887037151, center_i: 0.131825904205330,
+            zoom: 1e9,
+            max_iterations: 2000,
+            .. FractalCfg::default()
+        },
+    ]
+}
+
+// fused multiply-add rounds differently, and over enough iterations close to
+// the boundary that grows into a different orbit. pixels that escape early
+// haven't had the time to drift, so those have to agree to within an
+// iteration, whichever of the two they escaped early in
+fn assert_fma_close(buf: &[f32], reference: &[f32], what: &str) {
+    assert_eq!(buf.len(), reference.len());
+    for (i, (&a, &b)) in buf.iter().zip(reference.iter()).enumerate() {
+        let early = |x: f32| x >= 0f32 && x < 64f32;
+        if early(a) || early(b) {
+            assert!(a >= 0f32 && b >= 0f32 && (a - b).abs() <= 1f32,
+                    "{}: pixel {} is {} with fma and {} without", what, i, a, b);
+        }
+    }
+}
+
+#[test]
+fn test_scalar_always_available() {
+    assert!(Backend::available().contains(&Backend::Scalar));
+    assert!(Backend::detect().is_supported());
+}
+
+#[test]
+fn test_backends_match_scalar() {
+    for cfg in test_cfgs() {
+        let reference = mandelbrot_with_backend(&cfg, Backend::Scalar);
+        for backend in Backend::available() {
+            let buf = mandelbrot_with_backend(&cfg, backend);
+            assert_eq!(buf.len(), reference.len());
+            if backend == Backend::Avx2Fma {
+                assert_fma_close(&buf, &reference, &format!("{}x{} at {} {} zoom {}", cfg.width, cfg.height, cfg.center_r, cfg.center_i, cfg.zoom));
+            } else {
+                assert_eq!(buf, reference, "{:?}", backend);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_auto_precision() {
+    let shallow = FractalCfg::default();
+    assert!(!shallow.use_double());
+    let deep = FractalCfg { center_r: -0.75, zoom: 1e7, .. FractalCfg::default() };
+    assert!(deep.use_double());
+    let forced = FractalCfg { center_r: -0.75, zoom: 1e7, precision: Precision::Single, .. FractalCfg::default() };
+    assert!(!forced.use_double());
+}
+
+#[test]
+fn test_double_resolves_deep_zoom() {
+    // at this zoom f32 can't tell neighbouring pixels apart, so whole rows
+    // come out identical
+    let cfg = FractalCfg {
+        width: 64, height: 64,
+        center_r: -0.743643887037151, center_i: 0.131825904205330,
+        zoom: 1e9,
+        max_iterations: 2000,
+        .. FractalCfg::default()
+    };
+    let count_distinct = |buf: &Vec<f32>| {
+        let mut row: Vec<_> = buf[..64].iter().map(|x| x.to_bits()).collect();
+        row.sort();
+        row.dedup();
+        row.len()
+    };
+    let single = mandelbrot(&FractalCfg { precision: Precision::Single, .. cfg.clone() });
+    let double = mandelbrot(&cfg);
+    assert!(count_distinct(&single) < 8);
+    assert!(count_distinct(&double) > 32);
+}
+
+#[test]
+fn test_threads_match_serial() {
+    let mut cfgs = test_cfgs();
+    cfgs.push(FractalCfg {
+        width: 64, height: 64,
+        center_r: -0.743643887037151, center_i: 0.131825904205330,
+        zoom: 1e6,
+        max_iterations: 2000,
+        precision: Precision::Perturbation,
+        .. FractalCfg::default()
+    });
+    for cfg in cfgs {
+        let serial = mandelbrot(&FractalCfg { threads: 1, .. cfg.clone() });
+        for &threads in [0, 3].iter() {
+            let buf = mandelbrot(&FractalCfg { threads: threads, .. cfg.clone() });
+            assert_eq!(buf, serial, "{} threads", threads);
+        }
+    }
+}
+
+#[test]
+fn test_any_width() {
+    for width in 1..20 {
+        for &precision in [Precision::Single, Precision::Double, Precision::Perturbation].iter() {
+            let cfg = FractalCfg {
+                width: width, height: 7,
+                center_r: -0.5,
+                precision: precision,
+                .. FractalCfg::default()
+            };
+            let reference = mandelbrot_with_backend(&cfg, Backend::Scalar);
+            assert_eq!(reference.len(), (width * 7) as usize);
+            for backend in Backend::available() {
+                let buf = mandelbrot_with_backend(&cfg, backend);
+                if backend == Backend::Avx2Fma {
+                    assert_fma_close(&buf, &reference, &format!("{:?} width {}", precision, width));
+                } else {
+                    assert_eq!(buf, reference, "{:?} {:?} width {}", backend, precision, width);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_bad_size() {
+    assert!(FractalCfg { width: 0, .. FractalCfg::default() }.validate().is_err());
+    assert!(FractalCfg { height: 0, .. FractalCfg::default() }.validate().is_err());
+    assert!(FractalCfg { width: 1, height: 1, .. FractalCfg::default() }.validate().is_ok());
+    let cfg = FractalCfg { width: 0, .. FractalCfg::default() };
+    assert!(write_fractal(&cfg, "empty.png", None, true).is_err());
+}
@@ -0,0 +1,44 @@
+extern crate fractals;
+use fractals::*;
+
+fn cfg() -> FractalCfg {
+    FractalCfg {
+        width: 64, height: 45,
+        center_r: -0.5,
+        precision: Precision::Double,
+        .. FractalCfg::default()
+    }
+}
+
+#[test]
+fn test_iterations_dont_change_escape_times() {
+    let cfg = cfg();
+    let a = mandelbrot_with_backend(&cfg, Backend::Scalar);
+    let b = mandelbrot_with_backend(&FractalCfg { max_iterations: 1000, .. cfg.clone() }, Backend::Scalar);
+    for (a, b) in a.iter().zip(b.iter()) {
+        // only the pixels that hadn't escaped yet change
+        if *a >= 0f32 {
+            assert_eq!(a, b);
+        }
+    }
+}
+
+#[test]
+fn test_smooth_iter_independent_of_bailout() {
+    let cfg = cfg();
+    let reference = mandelbrot_with_backend(&cfg, Backend::Scalar);
+    for &bailout in &[16.0, 1000.0, 1e6] {
+        let buf = mandelbrot_with_backend(&FractalCfg { bailout: bailout, .. cfg.clone() }, Backend::Scalar);
+        for (a, b) in reference.iter().zip(buf.iter()) {
+            assert_eq!(*a < 0f32, *b < 0f32);
+            // log|z| only doubles exactly once |z| is much bigger than c
+            assert!((a - b).abs() < 0.05, "bailout {}: {} vs {}", bailout, a, b);
+        }
+    }
+}
+
+#[test]
+fn test_bad_bailout() {
+    assert!(FractalCfg { bailout: 1.0, .. FractalCfg::default() }.validate().is_err());
+    assert!(FractalCfg { bailout: 2.0, .. FractalCfg::default() }.validate().is_ok());
+}
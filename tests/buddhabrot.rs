@@ -0,0 +1,77 @@
+extern crate fractals;
+use fractals::*;
+
+fn cfg() -> FractalCfg {
+    FractalCfg {
+        width: 40, height: 30,
+        max_iterations: 200,
+        formula: Formula::Buddhabrot,
+        samples: 64,
+        seed: 7,
+        .. FractalCfg::default()
+    }
+}
+
+#[test]
+fn test_buddhabrot_is_reproducible() {
+    let a = buddhabrot(&FractalCfg { threads: 1, .. cfg() });
+    let b = buddhabrot(&FractalCfg { threads: 3, .. cfg() });
+    assert_eq!(a.channels, b.channels);
+    assert!(a.channels[0].iter().any(|&x| x > 0f32));
+    assert_eq!(mandelbrot(&cfg()), a.total());
+
+    let c = buddhabrot(&FractalCfg { seed: 8, .. cfg() });
+    assert!(a.channels != c.channels);
+}
+
+#[test]
+fn test_buddhabrot_is_symmetric() {
+    // orbits of conj(c) are the conjugates of orbits of c, so with enough
+    // samples the top and bottom halves look the same
+    let cfg = FractalCfg { width: 20, height: 21, samples: 2000, .. cfg() };
+    let buf = mandelbrot(&cfg);
+    let (w, h) = (cfg.width as usize, cfg.height as usize);
+    // the point of row y (counting up from the bottom) is -2 + 4*y/h, so y
+    // mirrors to h - y
+    let row_sum = |y: usize| -> f32 { buf[(h - y - 1)*w..(h - y)*w].iter().sum() };
+    for y in 1..(h/2 + 1) {
+        let (a, b) = (row_sum(y), row_sum(h - y));
+        assert!((a - b).abs() < 0.05 * (a + b), "row {}: {} vs {}", y, a, b);
+    }
+}
+
+#[test]
+fn test_nebulabrot_channels() {
+    let cfg = FractalCfg { channel_iterations: vec![500, 50, 5], .. cfg() };
+    let density = buddhabrot(&cfg);
+    assert_eq!(density.channels.len(), 3);
+    // a longer limit counts every orbit a shorter one does, and then some
+    for i in 0..density.channels[0].len() {
+        assert!(density.channels[0][i] >= density.channels[1][i]);
+        assert!(density.channels[1][i] >= density.channels[2][i]);
+    }
+    assert!(FractalCfg { channel_iterations: vec![500, 50], .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { samples: 0, .. cfg }.validate().is_err());
+}
+
+#[test]
+fn test_buddhabrot_julia_escape_radius() {
+    // the julia set of c = -2.5 is dust along the real line out to about
+    // +-2.158, so orbits that get past 2 can still come back
+    let cfg = FractalCfg {
+        width: 80, height: 80,
+        zoom: 0.5,
+        julia: true,
+        cr: -2.5, ci: 0.0,
+        .. cfg()
+    };
+    let buf = mandelbrot(&cfg);
+    // columns 0.1 wide from -4 to 4, and the ones past +-2.05 still get
+    // visited
+    let (w, h) = (cfg.width as usize, cfg.height as usize);
+    let outside: f32 = (0..h)
+        .flat_map(|y| (0..20).chain(61..w).map(move |x| y * w + x))
+        .map(|i| buf[i])
+        .sum();
+    assert!(outside > 0f32);
+}
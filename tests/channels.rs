@@ -0,0 +1,112 @@
+extern crate fractals;
+use fractals::*;
+
+fn cfg() -> FractalCfg {
+    FractalCfg {
+        width: 64, height: 45,
+        center_r: -0.5,
+        max_iterations: 500,
+        precision: Precision::Double,
+        .. FractalCfg::default()
+    }
+}
+
+#[test]
+fn test_parse_channels() {
+    assert_eq!("".parse::<Channels>(), Ok(Channels::default()));
+    assert_eq!("final-z, period".parse::<Channels>(),
+               Ok(Channels { final_z: true, period: true, .. Channels::default() }));
+    assert_eq!("final-z,angle,derivative,min-modulus,period,multiplier".parse::<Channels>(), Ok(Channels::all()));
+    assert!("curvature".parse::<Channels>().is_err());
+}
+
+#[test]
+fn test_channels_dont_change_value() {
+    let cfg = cfg();
+    let value = mandelbrot_with_backend(&cfg, Backend::Scalar);
+    for backend in Backend::available() {
+        let render = render_with_backend(&cfg, backend, Channels::all());
+        assert_eq!(render.channels(), Channels::all());
+        assert!(render.value == mandelbrot_with_backend(&cfg, backend), "{:?}", backend);
+    }
+    let render = render_with_backend(&cfg, Backend::Scalar, Channels::default());
+    assert_eq!(render.value, value);
+    assert_eq!(render.final_z, None);
+    assert_eq!(render.period, None);
+}
+
+#[test]
+fn test_orbit_channels() {
+    let cfg = cfg();
+    let render = render_with_backend(&cfg, Backend::Scalar, Channels::all());
+    let final_z = render.final_z.unwrap();
+    let min_modulus = render.min_modulus.unwrap();
+    let distance = mandelbrot_with_backend(&FractalCfg { distance_estimate: true, .. cfg.clone() }, Backend::Scalar);
+    let derivative = render.derivative.unwrap();
+    let pixel = 4.0 / (cfg.height as f32);
+    for i in 0..render.value.len() {
+        let (zr, zi) = final_z[i];
+        let z = zr.hypot(zi);
+        if render.value[i] < 0f32 {
+            assert!(z < cfg.bailout as f32);
+            continue;
+        }
+        assert!(z >= cfg.bailout as f32, "{} escaped at |z| = {}", i, z);
+        assert!(min_modulus[i] <= z);
+        // the distance estimate is |z| ln|z|^2 / |dz|
+        let (dr, di) = derivative[i];
+        let d = z * (z * z).ln() / dr.hypot(di) / pixel;
+        assert!((d - distance[i]).abs() <= 1e-3 * distance[i], "{} vs {}", d, distance[i]);
+    }
+}
+
+#[test]
+fn test_angle() {
+    let cfg = cfg();
+    let render = render_with_backend(&cfg, Backend::Scalar, Channels::all());
+    let final_z = render.final_z.unwrap();
+    let angle = render.angle.unwrap();
+    for i in 0..render.value.len() {
+        if render.value[i] < 0f32 {
+            assert!(angle[i].is_nan());
+        } else {
+            assert_eq!(angle[i], final_z[i].1.atan2(final_z[i].0));
+        }
+    }
+    // it doesn't need the orbits inside, so the interior checks stay on
+    let just_angle = Channels { angle: true, .. Channels::default() };
+    for backend in Backend::available() {
+        let a = render_with_backend(&cfg, backend, just_angle).angle.unwrap();
+        let b = render_with_backend(&cfg, backend, Channels::all()).angle.unwrap();
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!(x == y || (x.is_nan() && y.is_nan()), "{:?}: {} vs {}", backend, x, y);
+        }
+    }
+}
+
+#[test]
+fn test_period() {
+    // at a 2x2 image, pixel 1 is right at the center
+    let period_at = |r: f64, i: f64, channels: Channels| {
+        let cfg = FractalCfg {
+            width: 2, height: 2,
+            center_r: r, center_i: i,
+            zoom: 1e6,
+            max_iterations: 1000,
+            precision: Precision::Double,
+            .. FractalCfg::default()
+        };
+        render_with_backend(&cfg, Backend::Scalar, channels).period.unwrap()[1]
+    };
+    let just_period = Channels { period: true, .. Channels::default() };
+    // centers of the main cardioid, the period 2 bulb, the rabbit's bulb and
+    // the period 3 minibrot on the real axis
+    let centers = [(0.0, 0.0, 1), (-1.0, 0.0, 2), (-0.122561, 0.744862, 3), (-1.754878, 0.0, 3)];
+    for &(r, i, period) in centers.iter() {
+        assert_eq!(period_at(r, i, just_period), period, "{} {}", r, i);
+        // when the whole orbit is needed, cycles are only looked for
+        assert_eq!(period_at(r, i, Channels::all()), period, "{} {}", r, i);
+    }
+    // outside
+    assert_eq!(period_at(1.0, 1.0, just_period), 0);
+}
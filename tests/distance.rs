@@ -0,0 +1,64 @@
+extern crate fractals;
+use fractals::*;
+
+fn cfg() -> FractalCfg {
+    FractalCfg {
+        width: 64, height: 48, max_iterations: 500,
+        center_r: -0.5,
+        distance_estimate: true,
+        .. FractalCfg::default()
+    }
+}
+
+#[test]
+fn test_distance_estimate() {
+    let cfg = cfg();
+    let buf = mandelbrot_with_backend(&cfg, Backend::Scalar);
+    let iterations = mandelbrot_with_backend(&FractalCfg { distance_estimate: false, .. cfg.clone() }, Backend::Scalar);
+    // same inside, and positive outside
+    for (d, n) in buf.iter().zip(iterations.iter()) {
+        assert_eq!(*d < 0f32, *n < 0f32);
+    }
+    // c = 2 is 2 away from the set at -2 + ... well, from c = 0.25 it's 1.75.
+    // the estimate is within a factor of 4 of that
+    let (w, h) = (cfg.width as usize, cfg.height as usize);
+    let pixel = 4.0 / (h as f32);
+    let right_edge = buf[(h/2)*w + w - 1] * pixel;
+    let true_distance = -0.5 + 2.0 * (w as f32) / (h as f32) - pixel - 0.25;
+    assert!(right_edge > true_distance / 4.0 && right_edge < true_distance * 4.0,
+            "{} vs {}", right_edge, true_distance);
+
+    for backend in Backend::available() {
+        let other = mandelbrot_with_backend(&cfg, backend);
+        // only fma rounds differently
+        if backend != Backend::Avx2Fma {
+            assert!(other == buf, "{:?} differs from scalar", backend);
+            continue;
+        }
+        let n_diff = buf.iter().zip(other.iter())
+            .filter(|&(a, b)| (*a < 0f32) != (*b < 0f32) || (a - b).abs() > 0.01 * a.abs().max(1f32))
+            .count();
+        assert!(n_diff * 50 < buf.len(), "{:?} differs on {} pixels", backend, n_diff);
+    }
+}
+
+#[test]
+fn test_distance_scales_with_zoom() {
+    // in pixels, so zooming in 2x on the same point doubles it
+    let a = mandelbrot(&FractalCfg { center_r: 0.5, zoom: 4.0, precision: Precision::Double, .. cfg() });
+    let b = mandelbrot(&FractalCfg { center_r: 0.5, zoom: 8.0, precision: Precision::Double, .. cfg() });
+    let (w, h) = (64, 48);
+    // the pixel that sits right on the center, whatever the zoom
+    let (x, y) = (w/2, h/2 - 1);
+    assert!((b[y*w + x] / a[y*w + x] - 2.0).abs() < 0.01);
+}
+
+#[test]
+fn test_distance_julia() {
+    let cfg = FractalCfg { julia: true, cr: -0.8, ci: 0.156, center_r: 0.0, .. cfg() };
+    let buf = mandelbrot(&cfg);
+    assert!(buf.iter().any(|&x| x < 0f32));
+    assert!(buf.iter().all(|&x| x < 0f32 || x > 0f32));
+    assert!(FractalCfg { formula: Formula::BurningShip, .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { trap: Some(Trap::Point(0.0, 0.0)), .. cfg }.validate().is_err());
+}
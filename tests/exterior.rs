@@ -0,0 +1,36 @@
+extern crate fractals;
+use fractals::*;
+use fractals::colors::ExteriorShading;
+use std::f32::consts::PI;
+
+#[test]
+fn test_exterior_shading() {
+    // inside, above and below the real axis, and a field line
+    let angle = [std::f32::NAN, 1.0, -1.0, 0.0, PI];
+    let paint = |exterior| {
+        let mut rgb = vec![200u8; 3 * angle.len()];
+        ExteriorShading { exterior: exterior }.paint(&mut rgb, &angle);
+        rgb.chunks(3).map(|c| c[0]).collect::<Vec<_>>()
+    };
+    assert_eq!(paint(Exterior::Plain), vec![200; 5]);
+    let binary = paint(Exterior::Binary);
+    assert_eq!((binary[0], binary[1], binary[3], binary[4]), (200, 200, 200, 200));
+    assert!(binary[2] < 100);
+    let lines = paint(Exterior::FieldLines);
+    assert_eq!((lines[0], lines[4]), (200, 200));
+    assert!(lines[3] < lines[1] && lines[1] < lines[4]);
+    assert_eq!(lines[1], lines[2]);
+}
+
+#[test]
+fn test_exterior_cfg() {
+    assert_eq!("binary".parse::<Exterior>(), Ok(Exterior::Binary));
+    assert_eq!("field-lines".parse::<Exterior>(), Ok(Exterior::FieldLines));
+    assert!("rays".parse::<Exterior>().is_err());
+    let cfg = FractalCfg { exterior: Exterior::Binary, .. FractalCfg::default() };
+    assert!(cfg.validate().is_ok());
+    assert!(cfg.channels().angle);
+    assert!(!FractalCfg::default().channels().angle);
+    assert!(FractalCfg { formula: Formula::Newton, .. cfg.clone() }.validate().is_err());
+    assert_eq!(FractalCfg { precision: Precision::Perturbation, .. cfg }.resolve_precision(), Precision::Double);
+}
@@ -0,0 +1,225 @@
+extern crate fractals;
+extern crate clap;
+use fractals::*;
+use clap::{App, Arg};
+
+// the same image as the scalar backend, exactly, except with fused
+// multiply-add. that rounds differently, and close to the set, or wherever a
+// fold flips the sign of a part of z, that grows into a different orbit. so
+// only the pixels that escape right away have to agree to within an
+// iteration, and the rest mostly
+fn assert_matches_scalar(buf: &[f32], scalar: &[f32], backend: Backend, what: &str) {
+    if backend != Backend::Avx2Fma {
+        assert!(buf == scalar, "{} {:?} differs from scalar", what, backend);
+        return;
+    }
+    assert_eq!(buf.len(), scalar.len());
+    let close = |a: f32, b: f32| a >= 0f32 && b >= 0f32 && (a - b).abs() <= 1f32;
+    for (i, (&a, &b)) in buf.iter().zip(scalar.iter()).enumerate() {
+        let early = |x: f32| x >= 0f32 && x < 16f32;
+        if early(a) || early(b) {
+            assert!(close(a, b), "{}: pixel {} is {} with fma and {} without", what, i, a, b);
+        }
+    }
+    let n_diff = buf.iter().zip(scalar.iter())
+        .filter(|&(&a, &b)| !close(a, b) && !(a < 0f32 && b < 0f32))
+        .count();
+    assert!(n_diff * 50 < buf.len(), "{} with fma differs on {} pixels", what, n_diff);
+}
+
+#[test]
+fn test_multibrot_2_is_mandelbrot() {
+    let cfg = FractalCfg { width: 64, height: 48, max_iterations: 100, .. FractalCfg::default() };
+    let multi = FractalCfg { formula: Formula::Multibrot(2.0), .. cfg.clone() };
+    assert_eq!(mandelbrot(&cfg), mandelbrot(&multi));
+}
+
+#[test]
+fn test_parse_formula() {
+    assert_eq!("mandelbrot".parse::<Formula>(), Ok(Formula::Mandelbrot));
+    assert_eq!("multibrot:3".parse::<Formula>(), Ok(Formula::Multibrot(3.0)));
+    assert_eq!("multibrot:2.5".parse::<Formula>(), Ok(Formula::Multibrot(2.5)));
+    assert!("multibrot".parse::<Formula>().is_err());
+    assert_eq!("burningship".parse::<Formula>(), Ok(Formula::BurningShip));
+    assert!("celtic:3".parse::<Formula>().is_err());
+    assert!("foo".parse::<Formula>().is_err());
+}
+
+#[test]
+fn test_multibrot_backends_match_scalar() {
+    for &power in [3.0, 2.5].iter() {
+        let cfg = FractalCfg {
+            width: 61, height: 37, max_iterations: 100,
+            formula: Formula::Multibrot(power),
+            .. FractalCfg::default()
+        };
+        let scalar = mandelbrot_with_backend(&cfg, Backend::Scalar);
+        assert!(scalar.iter().any(|&x| x < 0f32));
+        assert!(scalar.iter().any(|&x| x >= 0f32));
+        for backend in Backend::available() {
+            let buf = mandelbrot_with_backend(&cfg, backend);
+            assert_matches_scalar(&buf, &scalar, backend, &format!("multibrot:{}", power));
+        }
+    }
+}
+
+#[test]
+fn test_bad_multibrot_power() {
+    let cfg = FractalCfg { formula: Formula::Multibrot(0.5), .. FractalCfg::default() };
+    assert!(cfg.validate().is_err());
+    // would take forever by repeated multiplication
+    for &d in [1e12, std::f64::INFINITY, std::f64::NAN].iter() {
+        assert!(FractalCfg { formula: Formula::Multibrot(d), .. cfg.clone() }.validate().is_err(), "{}", d);
+        assert!(FractalCfg { formula: Formula::Multicorn(d), .. cfg.clone() }.validate().is_err(), "{}", d);
+    }
+    assert!(FractalCfg { formula: Formula::Multibrot(64.0), .. cfg.clone() }.validate().is_ok());
+}
+
+#[test]
+fn test_formula_option() {
+    let matches = |args: &[&str]| App::new("test")
+        .arg(Arg::with_name("formula").long("formula").takes_value(true))
+        .arg(Arg::with_name("iter").long("iter").takes_value(true))
+        .get_matches_from(args.iter());
+    let cfg = FractalCfg::from_matches(&matches(&["test", "--formula", "celtic"])).unwrap();
+    assert_eq!(cfg.formula, Formula::Celtic);
+    let cfg = FractalCfg::from_matches(&matches(&["test"])).unwrap();
+    assert_eq!(cfg.formula, Formula::Mandelbrot);
+    // a typo is an error, not the default
+    assert!(FractalCfg::from_matches(&matches(&["test", "--formula", "burningship2"])).is_err());
+    assert!(FractalCfg::from_matches(&matches(&["test", "--iter", "abc"])).is_err());
+}
+
+#[test]
+fn test_abs_variants_backends_match_scalar() {
+    let formulas = [Formula::BurningShip, Formula::Celtic, Formula::Buffalo, Formula::Perpendicular];
+    for &formula in formulas.iter() {
+        for &julia in [false, true].iter() {
+            let cfg = FractalCfg {
+                width: 61, height: 37, max_iterations: 100,
+                center_r: -0.4, center_i: -0.5, zoom: 1.5,
+                cr: -0.6, ci: -0.3,
+                julia: julia,
+                formula: formula,
+                .. FractalCfg::default()
+            };
+            let scalar = mandelbrot_with_backend(&cfg, Backend::Scalar);
+            assert!(scalar.iter().any(|&x| x < 0f32), "{:?} has no interior", formula);
+            for backend in Backend::available() {
+                let buf = mandelbrot_with_backend(&cfg, backend);
+                assert_matches_scalar(&buf, &scalar, backend, &format!("{:?} julia {}", formula, julia));
+            }
+        }
+    }
+    // the perturbation renderer only iterates z^2 + c
+    let cfg = FractalCfg { formula: Formula::BurningShip, precision: Precision::Perturbation, .. FractalCfg::default() };
+    assert_eq!(cfg.resolve_precision(), Precision::Double);
+}
+
+// escapes or not, iterating conj(z)^d + c directly
+fn multicorn_escapes(mut zr: f64, mut zi: f64, cr: f64, ci: f64, d: u32, max_iterations: u32, bailout: f64) -> bool {
+    for _ in 0..max_iterations {
+        let (ar, ai) = (zr, -zi);
+        let (mut pr, mut pi) = (ar, ai);
+        for _ in 1..d {
+            let r = pr * ar - pi * ai;
+            pi = pr * ai + pi * ar;
+            pr = r;
+        }
+        zr = pr + cr;
+        zi = pi + ci;
+        if zr * zr + zi * zi >= bailout * bailout {
+            return true;
+        }
+    }
+    false
+}
+
+#[test]
+fn test_tricorn() {
+    assert_eq!("tricorn".parse::<Formula>(), Ok(Formula::Multicorn(2.0)));
+    assert_eq!("multicorn:3".parse::<Formula>(), Ok(Formula::Multicorn(3.0)));
+    for &(power, bailout) in [(2, 256.0), (3, 256.0), (2, 2.0), (3, 4.0)].iter() {
+        for &julia in [false, true].iter() {
+            let cfg = FractalCfg {
+                width: 60, height: 40, max_iterations: 100,
+                bailout: bailout,
+                cr: -0.1, ci: 0.2,
+                julia: julia,
+                formula: Formula::Multicorn(power as f64),
+                .. FractalCfg::default()
+            };
+            let (w, h) = (cfg.width as usize, cfg.height as usize);
+            let (xwidth, ywidth) = (4.0 * w as f64 / h as f64, 4.0);
+            for backend in Backend::available() {
+                let buf = mandelbrot_with_backend(&cfg, backend);
+                assert!(buf.iter().any(|&x| x < 0f32));
+                let n_diff = (0..h).flat_map(|row| (0..w).map(move |x| (x, row)))
+                    .filter(|&(x, row)| {
+                        let pr = x as f64 * xwidth / w as f64 - xwidth / 2.0;
+                        let pi = (h - row - 1) as f64 * ywidth / h as f64 - ywidth / 2.0;
+                        let escapes = if julia {
+                            multicorn_escapes(pr, pi, cfg.cr, cfg.ci, power, cfg.max_iterations, cfg.bailout)
+                        } else {
+                            multicorn_escapes(pr, pi, pr, pi, power, cfg.max_iterations, cfg.bailout)
+                        };
+                        escapes != (buf[row*w + x] >= 0f32)
+                    })
+                    .count();
+                assert!(n_diff * 50 < buf.len(), "multicorn:{} bailout {} {:?} differs on {} pixels", power, bailout, backend, n_diff);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_phoenix() {
+    assert_eq!("phoenix".parse::<Formula>(), Ok(Formula::Phoenix(-0.5, 0.0)));
+    assert_eq!("phoenix:0.1-0.2i".parse::<Formula>(), Ok(Formula::Phoenix(0.1, -0.2)));
+    let cfg = FractalCfg { width: 61, height: 37, max_iterations: 100, .. FractalCfg::default() };
+    // without the previous z it's the plain mandelbrot set
+    let phoenix = FractalCfg { formula: Formula::Phoenix(0.0, 0.0), .. cfg.clone() };
+    assert_eq!(mandelbrot(&cfg), mandelbrot(&phoenix));
+
+    let cfg = FractalCfg {
+        julia: true, cr: 0.5667, ci: 0.0,
+        formula: Formula::Phoenix(-0.5, 0.0),
+        .. cfg
+    };
+    let scalar = mandelbrot_with_backend(&cfg, Backend::Scalar);
+    assert!(scalar.iter().any(|&x| x < 0f32));
+    assert!(scalar.iter().any(|&x| x >= 0f32));
+    for backend in Backend::available() {
+        let buf = mandelbrot_with_backend(&cfg, backend);
+        assert_matches_scalar(&buf, &scalar, backend, "phoenix");
+    }
+}
+
+#[test]
+fn test_lyapunov() {
+    let cfg = FractalCfg {
+        width: 64, height: 64,
+        max_iterations: 400,
+        center_r: 3.0, center_i: 3.0, zoom: 2.0,
+        formula: Formula::Lyapunov,
+        sequence: "AABAB".to_owned(),
+        .. FractalCfg::default()
+    };
+    let buf = mandelbrot(&cfg);
+    assert!(buf.iter().all(|x| x.is_finite()));
+    assert!(buf.iter().any(|&x| x < 0f32));
+    assert!(buf.iter().any(|&x| x > 0f32));
+    // rates below 3 settle on a fixed point, 4 is chaotic
+    let (w, h) = (cfg.width as usize, cfg.height as usize);
+    assert!(buf[(h - 1)*w] < 0f32);
+    assert!(buf[w - 1] > 0f32);
+
+    // the sign survives normalizing, the pixels past a rate of 4 are NaN
+    let normalized = normalize_signed(buf.clone(), 1.0, 0.0);
+    assert!(buf.iter().zip(normalized.iter()).all(|(a, b)| (*a < 0f32) == b.is_sign_negative()));
+    let buf = mandelbrot(&FractalCfg { center_r: 4.0, .. cfg.clone() });
+    assert!(buf.iter().any(|x| x.is_nan()));
+
+    assert!(FractalCfg { sequence: "ABC".to_owned(), .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { sequence: "".to_owned(), .. cfg }.validate().is_err());
+}
@@ -0,0 +1,172 @@
+extern crate fractals;
+use fractals::*;
+
+fn test_cfgs() -> Vec<FractalCfg> {
+    vec![
+        FractalCfg { width: 64, height: 45, .. FractalCfg::default() },
+        FractalCfg {
+            width: 61, height: 37,
+            center_r: -0.5,
+            max_iterations: 2000,
+            .. FractalCfg::default()
+        },
+        FractalCfg {
+            width: 48, height: 64,
+            center_r: -0.743643, center_i: 0.131825,
+            zoom: 200.0,
+            max_iterations: 1000,
+            .. FractalCfg::default()
+        },
+        // the douady rabbit, which has a period 3 cycle
+        FractalCfg {
+            width: 64, height: 64,
+            julia: true,
+            cr: -0.123, ci: 0.745,
+            max_iterations: 1000,
+            .. FractalCfg::default()
+        },
+        FractalCfg {
+            width: 64, height: 45,
+            precision: Precision::Double,
+            max_iterations: 1000,
+            .. FractalCfg::default()
+        },
+        // odd sizes, so no pixel lands right on c = i or c = -2. their orbits
+        // hit a repelling cycle exactly, which is caught as periodic, but
+        // rounding eventually knocks the brute force orbit off of it
+        FractalCfg {
+            width: 63, height: 47,
+            center_r: -0.5,
+            max_iterations: 500,
+            distance_estimate: true,
+            .. FractalCfg::default()
+        },
+        FractalCfg {
+            width: 64, height: 64,
+            center_r: -0.5, center_i: -0.5,
+            formula: Formula::BurningShip,
+            .. FractalCfg::default()
+        },
+        FractalCfg {
+            width: 64, height: 64,
+            formula: Formula::Multibrot(3.0),
+            .. FractalCfg::default()
+        },
+    ]
+}
+
+fn brute_force(cfg: &FractalCfg) -> FractalCfg {
+    FractalCfg { bulb_check: false, periodicity_check: false, .. cfg.clone() }
+}
+
+#[test]
+fn test_interior_checks_match_brute_force() {
+    for cfg in test_cfgs() {
+        for backend in Backend::available() {
+            let reference = mandelbrot_with_backend(&brute_force(&cfg), backend);
+            for &(bulb_check, periodicity_check) in &[(true, false), (false, true), (true, true)] {
+                let buf = mandelbrot_with_backend(&FractalCfg {
+                    bulb_check: bulb_check,
+                    periodicity_check: periodicity_check,
+                    .. cfg.clone()
+                }, backend);
+                assert!(buf == reference, "{:?} {:?} bulb {} periodicity {}",
+                        cfg.formula, backend, bulb_check, periodicity_check);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_interior_checks_ignore_traps() {
+    // the trap distance needs the whole orbit, even inside
+    let cfg = FractalCfg {
+        width: 64, height: 45,
+        trap: Some(Trap::Circle(0.0, 0.0, 0.5)),
+        .. FractalCfg::default()
+    };
+    let buf = mandelbrot_with_backend(&cfg, Backend::Scalar);
+    assert!(buf == mandelbrot_with_backend(&brute_force(&cfg), Backend::Scalar));
+}
+
+// a 2x2 image, where pixel 1 is right at the center
+fn at(r: f64, i: f64, channels: Channels) -> Render {
+    let cfg = FractalCfg {
+        width: 2, height: 2,
+        center_r: r, center_i: i,
+        zoom: 1e6,
+        max_iterations: 1000,
+        precision: Precision::Double,
+        .. FractalCfg::default()
+    };
+    render_with_backend(&cfg, Backend::Scalar, channels)
+}
+
+#[test]
+fn test_multiplier() {
+    let channels = Channels { multiplier: true, .. Channels::default() };
+    let multiplier = |r: f64, i: f64| {
+        let (re, im) = at(r, i, channels).multiplier.unwrap()[1];
+        (re as f64, im as f64)
+    };
+    // the centers of components have superattracting cycles
+    for &(r, i) in [(0.0, 0.0), (-1.0, 0.0), (-0.122561, 0.744862), (-1.754878, 0.0)].iter() {
+        let (re, im) = multiplier(r, i);
+        assert!(re.hypot(im) < 1e-4, "{} {}: {} {}", r, i, re, im);
+    }
+    // fixed point z = (1 - sqrt(1 - 4c))/2, with multiplier 2z
+    let (re, im) = multiplier(0.2, 0.0);
+    assert!((re - (1.0 - 0.2f64.sqrt())).abs() < 1e-4 && im.abs() < 1e-4, "{} {}", re, im);
+    // 4(c + 1) in the period 2 bulb
+    let (re, im) = multiplier(-1.1, 0.1);
+    assert!((re + 0.4).abs() < 1e-4 && (im - 0.4).abs() < 1e-4, "{} {}", re, im);
+    // outside
+    assert_eq!(multiplier(1.0, 1.0), (0.0, 0.0));
+}
+
+#[test]
+fn test_bulb_multipliers_match_iterated() {
+    // the bulb check fills in the multipliers of the two biggest components
+    // without iterating
+    let cfg = FractalCfg {
+        width: 64, height: 45,
+        center_r: -0.5,
+        max_iterations: 2000,
+        precision: Precision::Double,
+        .. FractalCfg::default()
+    };
+    let channels = Channels { period: true, multiplier: true, .. Channels::default() };
+    let a = render_with_backend(&cfg, Backend::Scalar, channels);
+    let b = render_with_backend(&FractalCfg { bulb_check: false, .. cfg.clone() }, Backend::Scalar, channels);
+    let (pa, pb) = (a.period.unwrap(), b.period.unwrap());
+    assert!(pa.iter().any(|&p| p == 1) && pa.iter().any(|&p| p == 2));
+    // at the edge of a component the multiplier is close to a root of unity,
+    // and an orbit that hardly converges can look like a cycle that goes
+    // around a few times, or like no cycle at all. the bulb check knows better
+    for (&a, &b) in pa.iter().zip(pb.iter()) {
+        assert!(b == 0 || b % a == 0, "{} vs {}", a, b);
+    }
+    let (ma, mb) = (a.multiplier.unwrap(), b.multiplier.unwrap());
+    for i in 0..pa.len() {
+        if pa[i] == pb[i] {
+            let (x, y) = (ma[i], mb[i]);
+            assert!((x.0 - y.0).hypot(x.1 - y.1) < 1e-3, "{:?} vs {:?}", x, y);
+        }
+    }
+}
+
+#[test]
+fn test_interior_validation() {
+    let cfg = FractalCfg { interior: Interior::Period, .. FractalCfg::default() };
+    assert!(cfg.validate().is_ok());
+    assert!(FractalCfg { formula: Formula::BurningShip, .. cfg.clone() }.validate().is_ok());
+    assert!(FractalCfg { formula: Formula::Phoenix(-0.5, 0.0), .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { formula: Formula::Newton, .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { periodicity_check: false, .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { trap: Some(Trap::Point(0.0, 0.0)), .. cfg.clone() }.validate().is_err());
+    let cfg = FractalCfg { interior: Interior::Multiplier, .. cfg };
+    assert!(cfg.validate().is_ok());
+    assert!(FractalCfg { formula: Formula::BurningShip, .. cfg.clone() }.validate().is_err());
+    assert_eq!("multiplier".parse::<Interior>(), Ok(Interior::Multiplier));
+    assert!("white".parse::<Interior>().is_err());
+}
@@ -0,0 +1,65 @@
+extern crate fractals;
+use fractals::*;
+
+fn cfg(formula: Formula) -> FractalCfg {
+    FractalCfg {
+        width: 80, height: 60,
+        julia: true,
+        // the basilica
+        cr: -1.0, ci: 0.0,
+        formula: formula,
+        max_iterations: 1000,
+        .. FractalCfg::default()
+    }
+}
+
+#[test]
+fn test_parse_inverse() {
+    assert_eq!("iim".parse::<Formula>(), Ok(Formula::Iim));
+    assert_eq!("miim".parse::<Formula>(), Ok(Formula::Miim(10)));
+    assert_eq!("miim:50".parse::<Formula>(), Ok(Formula::Miim(50)));
+    assert!("miim:x".parse::<Formula>().is_err());
+    assert!(cfg(Formula::Miim(0)).validate().is_err());
+    assert!(FractalCfg { julia: false, .. cfg(Formula::Iim) }.validate().is_err());
+    assert!(FractalCfg { samples: 0, .. cfg(Formula::Iim) }.validate().is_err());
+    assert!(FractalCfg { relief: Some(Relief::default()), .. cfg(Formula::Iim) }.validate().is_err());
+}
+
+#[test]
+fn test_points_are_on_the_julia_set() {
+    let distance = mandelbrot(&FractalCfg { distance_estimate: true, .. cfg(Formula::Mandelbrot) });
+    for &formula in [Formula::Iim, Formula::Miim(5)].iter() {
+        let hits = mandelbrot(&cfg(formula));
+        let n_hit = hits.iter().filter(|&&x| x > 0f32).count();
+        assert!(n_hit > 100, "{:?} hit {} pixels", formula, n_hit);
+        for (i, (&h, &d)) in hits.iter().zip(distance.iter()).enumerate() {
+            // a point of the set is within half a diagonal of the pixel
+            // center, and the estimate is within a factor of 4 of that
+            if h > 0f32 {
+                assert!(d < 3f32, "{:?} hit pixel {}, {} pixels away", formula, i, d);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_inverse_deterministic() {
+    for &formula in [Formula::Iim, Formula::Miim(5)].iter() {
+        let cfg = FractalCfg { zoom: 3.0, center_r: 0.5, .. cfg(formula) };
+        let a = mandelbrot(&FractalCfg { threads: 1, .. cfg.clone() });
+        let b = mandelbrot(&FractalCfg { threads: 3, .. cfg.clone() });
+        assert!(a == b, "{:?}", formula);
+        assert!(a.iter().any(|&x| x > 0f32));
+    }
+}
+
+#[test]
+fn test_dust() {
+    // c is outside the mandelbrot set, so the julia set is dust that the
+    // escape times miss
+    let cfg = FractalCfg { cr: 0.4, ci: 0.4, max_iterations: 256, .. cfg(Formula::Mandelbrot) };
+    let escape = mandelbrot(&cfg);
+    assert!(escape.iter().all(|&x| x >= 0f32));
+    let hits = mandelbrot(&FractalCfg { formula: Formula::Miim(5), .. cfg });
+    assert!(hits.iter().filter(|&&x| x > 0f32).count() > 100);
+}
@@ -1,8 +1,3 @@
-#![feature(test)]
-extern crate test;
-use test::Bencher;
-use test::black_box;
-
 fn transform10(mag: f32, mx_f32x8: f32) -> f32 {
     let log_zn = mag.log10()/2f32;
     let nu = (log_zn / 2f32.log10()).log10() / 2f32.log10();
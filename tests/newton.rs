@@ -0,0 +1,103 @@
+extern crate fractals;
+use fractals::*;
+
+fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+    (a.0 - b.0).hypot(a.1 - b.1) < 1e-9
+}
+
+#[test]
+fn test_parse_polynomial() {
+    let p: Polynomial = "roots:1,i,-i".parse().unwrap();
+    // (z - 1)(z^2 + 1)
+    let expected = [(1.0, 0.0), (-1.0, 0.0), (1.0, 0.0), (-1.0, 0.0)];
+    assert_eq!(p.coefficients.len(), expected.len());
+    assert!(p.coefficients.iter().zip(expected.iter()).all(|(&a, &b)| close(a, b)));
+
+    let p: Polynomial = "coefs:1,-1-1i,0.5+2i".parse().unwrap();
+    assert_eq!(p.coefficients, vec![(1.0, 0.0), (-1.0, -1.0), (0.5, 2.0)]);
+    assert!("roots:".parse::<Polynomial>().is_err());
+    assert!("coefs:1,x".parse::<Polynomial>().is_err());
+    assert!("1,2,3".parse::<Polynomial>().is_err());
+
+    // built by hand, or read back from a config, with no coefficients at all
+    let empty = Polynomial { coefficients: vec![], roots: vec![] };
+    assert_eq!(empty.degree(), 0);
+    let cfg = FractalCfg { formula: Formula::Newton, polynomial: empty, .. FractalCfg::default() };
+    assert!(cfg.validate().is_err());
+    // or with the coefficients of z^3 - 1 but the roots left out, which nova
+    // starts from
+    let p = Polynomial::default();
+    let cfg = FractalCfg { polynomial: Polynomial { roots: vec![], .. p.clone() }, .. cfg };
+    assert!(cfg.validate().is_err());
+    assert!(FractalCfg { formula: Formula::Nova(1.0), .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { polynomial: p, .. cfg }.validate().is_ok());
+}
+
+#[test]
+fn test_roots_from_coefficients() {
+    // z^3 - 1, leading zeros are dropped
+    let p: Polynomial = "coefs:0,1,0,0,-1".parse().unwrap();
+    assert_eq!(p.degree(), 3);
+    let s = 3f64.sqrt() / 2.0;
+    for &r in [(1.0, 0.0), (-0.5, s), (-0.5, -s)].iter() {
+        assert!(p.roots.iter().any(|&x| close(x, r)), "{:?} not in {:?}", r, p.roots);
+    }
+}
+
+#[test]
+fn test_newton_basins() {
+    let cfg = FractalCfg {
+        width: 64, height: 64,
+        max_iterations: 100,
+        formula: Formula::Newton,
+        polynomial: "roots:1,-1,i,-i".parse().unwrap(),
+        .. FractalCfg::default()
+    };
+    let image = newton(&cfg);
+    // every root gets a basin, and the pixels next to a root go to it
+    for i in 0..4 {
+        assert!(image.roots.iter().any(|&r| r == i));
+    }
+    let (w, h) = (cfg.width as usize, cfg.height as usize);
+    // x = 1 is three quarters of the way across, y = 0 is the middle row
+    assert_eq!(image.roots[(h/2)*w + 3*w/4], 0);
+    assert_eq!(image.roots[(h/2)*w + w/4], 1);
+    // rows go top to bottom
+    assert_eq!(image.roots[(h/4)*w + w/2], 2);
+    assert_eq!(image.roots[(3*h/4)*w + w/2], 3);
+    // pixels next to a root converge faster than most
+    let converged: Vec<f32> = image.iterations.iter().cloned().filter(|&x| x >= 0f32).collect();
+    let mean = converged.iter().sum::<f32>() / (converged.len() as f32);
+    assert!(image.iterations[(h/2)*w + 3*w/4] < mean);
+    assert_eq!(mandelbrot(&cfg), image.iterations);
+}
+
+#[test]
+fn test_newton_needs_degree_2() {
+    let cfg = FractalCfg {
+        formula: Formula::Newton,
+        polynomial: "roots:1".parse().unwrap(),
+        .. FractalCfg::default()
+    };
+    assert!(cfg.validate().is_err());
+}
+
+#[test]
+fn test_nova() {
+    assert_eq!("nova".parse::<Formula>(), Ok(Formula::Nova(1.0)));
+    assert_eq!("nova:0.5".parse::<Formula>(), Ok(Formula::Nova(0.5)));
+    let cfg = FractalCfg {
+        width: 48, height: 32,
+        max_iterations: 100,
+        formula: Formula::Nova(1.0),
+        .. FractalCfg::default()
+    };
+    // the julia version with c = 0 is plain newton
+    let julia = FractalCfg { julia: true, .. cfg.clone() };
+    let newton = FractalCfg { formula: Formula::Newton, .. cfg.clone() };
+    assert_eq!(mandelbrot(&julia), mandelbrot(&newton));
+
+    let buf = mandelbrot(&FractalCfg { center_r: -0.5, .. cfg });
+    assert!(buf.iter().any(|&x| x < 0f32));
+    assert!(buf.iter().any(|&x| x >= 0f32));
+}
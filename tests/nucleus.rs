@@ -0,0 +1,85 @@
+extern crate fractals;
+use fractals::*;
+
+fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+    (a.0 - b.0).hypot(a.1 - b.1) < 1e-12
+}
+
+#[test]
+fn test_find_period() {
+    assert_eq!(find_period((0.0, 0.0), 0.1, 100), Some(1));
+    assert_eq!(find_period((-1.0, 0.0), 0.1, 100), Some(2));
+    assert_eq!(find_period((-1.75, 0.0), 0.01, 100), Some(3));
+    assert_eq!(find_period((-1.7433, 0.0), 2e-4, 100), Some(17));
+    // nothing there
+    assert_eq!(find_period((1.0, 1.0), 0.1, 100), None);
+}
+
+#[test]
+fn test_find_nucleus() {
+    let n = find_nucleus((-1.75, 0.0), 3).unwrap();
+    assert_eq!(n.period, 3);
+    assert!(close(n.center, (-1.7548776662466927, 0.0)), "{:?}", n);
+    // turned the same way as the whole set, and about 50 times smaller
+    assert!(n.angle().abs() < 1e-6, "{:?}", n);
+    assert!(n.zoom() > 40.0 && n.zoom() < 60.0, "{:?}", n);
+    // the main cardioid is the whole set
+    let n = find_nucleus((0.1, 0.1), 1).unwrap();
+    assert!(close(n.center, (0.0, 0.0)) && close(n.size, (1.0, 0.0)), "{:?}", n);
+    // the rabbit's bulb, which is period 3 but not a minibrot
+    let n = find_nucleus((-0.12, 0.74), 3).unwrap();
+    assert!(close(n.center, (-0.12256116687665362, 0.7448617666197442)), "{:?}", n);
+    // newton's method lands on the period 2 bulb, whose period divides 4
+    assert_eq!(find_nucleus((-1.01, 0.0), 4), None);
+    assert_eq!(find_nucleus((0.0, 0.0), 0), None);
+}
+
+#[test]
+fn test_deep_nucleus() {
+    // near -2 the orbit's derivative is huge, so z_p only gets to about 1e-9
+    // in f64, but the nuclei are still there
+    let n = find_nucleus((-1.9999, 0.0), 20).unwrap();
+    assert_eq!(n.period, 20);
+    assert!((n.center.0 + 1.9999000055185383).abs() < 1e-12, "{:?}", n);
+    let n = find_nucleus((-1.99999999, 0.0), 30).unwrap();
+    assert_eq!(n.period, 30);
+    assert!((n.center.0 + 1.99999999).abs() < 1e-9, "{:?}", n);
+    // a long way from any period 40 nucleus, and newton's method still
+    // doesn't wander off to a bigger component
+    let n = find_nucleus((-1.9999, 0.0), 40).unwrap();
+    assert_eq!(n.period, 40);
+}
+
+#[test]
+fn test_nucleus_view() {
+    let n = find_nucleus((-1.7433, 0.0), 17).unwrap();
+    assert_eq!(n.period, 17);
+    let cfg = n.view(&FractalCfg {
+        width: 33, height: 33,
+        max_iterations: 5000,
+        precision: Precision::Double,
+        .. FractalCfg::default()
+    });
+    let buf = mandelbrot(&cfg);
+    // the nucleus is inside, and so is most of the middle of the view, but
+    // the edges are outside like they are for the whole set at zoom 1
+    assert!(buf[16 * 33 + 16] < 0f32);
+    let inside = buf.iter().filter(|&&x| x < 0f32).count();
+    assert!(inside > 33 * 33 / 20 && inside < 33 * 33 / 2, "{} inside", inside);
+    assert!(buf[0] >= 0f32 && buf[33 * 33 - 1] >= 0f32);
+}
+
+#[test]
+fn test_find_misiurewicz() {
+    let m = find_misiurewicz((0.01, 0.99), 2, 2).unwrap();
+    assert_eq!((m.preperiod, m.period), (2, 2));
+    assert!(close(m.point, (0.0, 1.0)), "{:?}", m);
+    let m = find_misiurewicz((-1.99, 0.0), 2, 1).unwrap();
+    assert_eq!((m.preperiod, m.period), (2, 1));
+    assert!(close(m.point, (-2.0, 0.0)), "{:?}", m);
+    // asked for too long a cycle, and it comes back with the real one
+    let m = find_misiurewicz((0.01, 0.99), 2, 4).unwrap();
+    assert_eq!((m.preperiod, m.period), (2, 2));
+    // with preperiod 0, 0 itself is on the cycle, which makes c a nucleus
+    assert_eq!(find_misiurewicz((0.0, 0.0), 0, 1), None);
+}
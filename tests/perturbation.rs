@@ -0,0 +1,136 @@
+extern crate fractals;
+use fractals::*;
+
+// near the boundary the orbits are chaotic, so the two methods (like any two
+// ways of rounding) drift apart on a few pixels
+fn count_mismatches(a: &[f32], b: &[f32]) -> usize {
+    a.iter().zip(b.iter())
+        .filter(|&(a, b)| (*a < 0f32) != (*b < 0f32) || (a - b).abs() > 0.05 * a.abs().max(1f32))
+        .count()
+}
+
+fn count_distinct(buf: &[f32]) -> usize {
+    let mut xs: Vec<_> = buf.iter().map(|x| x.to_bits()).collect();
+    xs.sort();
+    xs.dedup();
+    xs.len()
+}
+
+#[test]
+fn test_perturbation_matches_double() {
+    let cfg = FractalCfg {
+        width: 64, height: 64,
+        center_r: -0.743643887037151, center_i: 0.131825904205330,
+        zoom: 1e6,
+        max_iterations: 2000,
+        precision: Precision::Double,
+        .. FractalCfg::default()
+    };
+    let double = mandelbrot(&cfg);
+    let perturbed = mandelbrot(&FractalCfg { precision: Precision::Perturbation, .. cfg });
+    let n_diff = count_mismatches(&double, &perturbed);
+    assert!(n_diff * 100 < double.len(), "{} pixels differ", n_diff);
+}
+
+#[test]
+fn test_perturbation_julia_matches_double() {
+    let cfg = FractalCfg {
+        width: 64, height: 64,
+        julia: true,
+        cr: -0.8, ci: 0.156,
+        center_r: 0.1, center_i: 0.2,
+        zoom: 1e3,
+        max_iterations: 1000,
+        precision: Precision::Double,
+        .. FractalCfg::default()
+    };
+    let double = mandelbrot(&cfg);
+    let perturbed = mandelbrot(&FractalCfg { precision: Precision::Perturbation, .. cfg });
+    let n_diff = count_mismatches(&double, &perturbed);
+    assert!(n_diff * 100 < double.len(), "{} pixels differ", n_diff);
+}
+
+#[test]
+fn test_reference_escapes_first() {
+    // c = 0.3 escapes after a dozen iterations, long before the set to its
+    // left does. with one reference there's no better one to move on to
+    let cfg = FractalCfg {
+        width: 64, height: 64,
+        center_r: 0.3, center_i: 0.0,
+        zoom: 1.0,
+        max_iterations: 500,
+        precision: Precision::Double,
+        .. FractalCfg::default()
+    };
+    let double = mandelbrot(&cfg);
+    let cfg = FractalCfg { precision: Precision::Perturbation, references: 1, .. cfg };
+    let (perturbed, stats) = mandelbrot_with_stats(&cfg, Backend::Scalar);
+    assert_eq!(stats.references, 1);
+    assert!(perturbed.iter().all(|x| x.is_finite()));
+    let n_diff = count_mismatches(&double, &perturbed);
+    assert!(n_diff * 100 < double.len(), "{} pixels differ", n_diff);
+    assert!(FractalCfg { references: 0, .. cfg }.validate().is_err());
+}
+
+#[test]
+fn test_deep_zoom_from_strings() {
+    // far past what f64 can resolve, only the strings have enough digits
+    let cfg = FractalCfg {
+        width: 32, height: 32,
+        center_r: -1.7490930547296868,
+        center_i: 0.0,
+        center_r_str: "-1.74909305472968678339081234583816637102691".to_owned(),
+        center_i_str: "0.00000000000000000000000000000000000000001".to_owned(),
+        zoom: 1e30,
+        max_iterations: 5000,
+        .. FractalCfg::default()
+    };
+    assert_eq!(cfg.resolve_precision(), Precision::Perturbation);
+    let buf = mandelbrot(&cfg);
+    assert!(count_distinct(&buf) > 100);
+}
+
+#[test]
+fn test_series_approximation_skips_iterations() {
+    let cfg = FractalCfg {
+        width: 64, height: 64,
+        center_r_str: "-1.74909305472968678339081234583816637102691".to_owned(),
+        center_i_str: "0.00000000000000000000000000000000000000001".to_owned(),
+        zoom: 1e30,
+        max_iterations: 5000,
+        .. FractalCfg::default()
+    };
+    let (with_series, stats) = mandelbrot_with_stats(&cfg, Backend::Scalar);
+    assert!(stats.series_skipped > 0);
+
+    let cfg = FractalCfg { series_approximation: false, .. cfg };
+    let (without_series, stats) = mandelbrot_with_stats(&cfg, Backend::Scalar);
+    assert_eq!(stats.series_skipped, 0);
+    let n_diff = count_mismatches(&with_series, &without_series);
+    assert!(n_diff * 100 < with_series.len(), "{} pixels differ", n_diff);
+}
+
+#[test]
+fn test_bad_center() {
+    // exponents far past the precision would take forever to work out
+    for &(r, i) in [("0.5x", "0"), ("0", "--1"), ("inf", "0"), ("", "1e"),
+                    ("1e999999999", "0"), ("0", "-1e-999999999")].iter() {
+        let cfg = FractalCfg {
+            width: 8, height: 8,
+            center_r_str: r.to_owned(),
+            center_i_str: i.to_owned(),
+            .. FractalCfg::default()
+        };
+        assert!(cfg.validate().is_err(), "{:?} {:?}", r, i);
+        assert!(write_fractal(&cfg, "bad_center.png", None, true).is_err());
+    }
+    let cfg = FractalCfg { center_r_str: "-0.75".to_owned(), .. FractalCfg::default() };
+    assert_eq!(cfg.center(), Ok((-0.75, 0.0)));
+    // while ones the precision can hold are fine, and deeper zooms hold more
+    let cfg = FractalCfg { center_r_str: "-7.5e-1".to_owned(), center_i_str: "1e-20".to_owned(), .. cfg };
+    assert!(cfg.validate().is_ok());
+    let cfg = FractalCfg { center_i_str: "1e-50".to_owned(), .. cfg };
+    assert!(cfg.validate().is_err());
+    assert!(FractalCfg { zoom: 1e45, .. cfg.clone() }.validate().is_ok());
+    assert!(FractalCfg { zoom: std::f64::INFINITY, .. cfg }.validate().is_err());
+}
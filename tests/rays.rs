@@ -0,0 +1,116 @@
+extern crate fractals;
+extern crate imagefmt;
+use fractals::*;
+
+fn angle(s: &str) -> ExternalAngle {
+    s.parse().unwrap()
+}
+
+fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+    (a.0 - b.0).hypot(a.1 - b.1) < 1e-9
+}
+
+#[test]
+fn test_parse_angle() {
+    assert_eq!(angle("1/3"), ExternalAngle { num: 1, den: 3 });
+    assert_eq!(angle("2/6"), ExternalAngle { num: 1, den: 3 });
+    assert_eq!(angle("5/4"), ExternalAngle { num: 1, den: 4 });
+    assert_eq!(angle("0"), ExternalAngle { num: 0, den: 1 });
+    assert_eq!(angle("2/7").to_string(), "2/7");
+    assert!("1/0".parse::<ExternalAngle>().is_err());
+    assert!("x/3".parse::<ExternalAngle>().is_err());
+    assert!("-1/3".parse::<ExternalAngle>().is_err());
+    assert_eq!(angle("3/7").doubled(), angle("6/7"));
+    assert_eq!(angle("5/12").doubled(), angle("5/6"));
+}
+
+#[test]
+fn test_preperiod_period() {
+    assert_eq!(angle("0").preperiod_period(), (0, 1));
+    assert_eq!(angle("1/3").preperiod_period(), (0, 2));
+    assert_eq!(angle("2/7").preperiod_period(), (0, 3));
+    assert_eq!(angle("1/2").preperiod_period(), (1, 1));
+    assert_eq!(angle("1/6").preperiod_period(), (1, 2));
+    assert_eq!(angle("5/12").preperiod_period(), (2, 2));
+}
+
+#[test]
+fn test_landing_points() {
+    let rabbit = (-0.125, 27f64.sqrt() / 8.0);
+    let cases = [
+        // roots of the main cardioid and of bulbs hanging off it
+        ("0", (0.25, 0.0)),
+        ("1/3", (-0.75, 0.0)),
+        ("2/3", (-0.75, 0.0)),
+        ("1/7", rabbit),
+        ("2/7", rabbit),
+        // the root of the period 3 minibrot on the real line
+        ("3/7", (-1.75, 0.0)),
+        // misiurewicz points
+        ("1/2", (-2.0, 0.0)),
+        ("1/6", (0.0, 1.0)),
+    ];
+    for &(a, landing) in cases.iter() {
+        let ray = trace_ray(angle(a), 256, 0.0);
+        assert!(ray.exact, "{:?}", ray.landing);
+        assert!(close(ray.landing, landing), "{} landed at {:?}", a, ray.landing);
+        // from far out in towards the landing point. rays that land on roots
+        // get there slowly, so the last point traced is only close
+        let first = ray.points[0];
+        let last = *ray.points.last().unwrap();
+        assert!(first.0.hypot(first.1) > 1000.0);
+        assert!((last.0 - landing.0).hypot(last.1 - landing.1) < 0.05, "{} ended at {:?}", a, last);
+    }
+    // the conjugate angle lands on the conjugate point
+    let ray = trace_ray(angle("5/6"), 256, 0.0);
+    assert!(close(ray.landing, (0.0, -1.0)), "{:?}", ray.landing);
+}
+
+#[test]
+fn test_ray_resolution() {
+    // however deep it's allowed to go, it stops once it's within the
+    // resolution of where it lands. the ray at 1/3 comes in slowly, and going
+    // all the way would take minutes
+    let coarse = trace_ray(angle("1/3"), 1000000, 0.05);
+    assert!(coarse.exact);
+    assert!(coarse.points.len() < 8 * 100, "{} points", coarse.points.len());
+    let last = *coarse.points.last().unwrap();
+    assert!((last.0 + 0.75).hypot(last.1) <= 0.05, "ended at {:?}", last);
+    // the finer one keeps going to the depth
+    let fine = trace_ray(angle("1/3"), 64, 0.0);
+    assert_eq!(fine.points.len(), 1 + 8 * 64);
+}
+
+#[test]
+fn test_ray_overlay() {
+    let cfg = FractalCfg {
+        width: 41, height: 41,
+        .. FractalCfg::default()
+    };
+    assert!(FractalCfg { rays: vec![angle("1/3")], julia: true, .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { rays: vec![angle("1/3")], formula: Formula::BurningShip, .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { rays: vec![angle("1/3")], ray_depth: 0, .. cfg.clone() }.validate().is_err());
+
+    let dir = std::env::temp_dir();
+    let read = |cfg: &FractalCfg, name: &str| {
+        let path = dir.join(name);
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(format!("{}.json", path));
+        write_fractal(cfg, path, None, true).unwrap();
+        imagefmt::read(path, imagefmt::ColFmt::RGB).unwrap().buf
+    };
+    let plain = read(&cfg, "rays_plain.png");
+    // the ray at angle 0 comes in along the real line to 1/4
+    let rays = read(&FractalCfg { rays: vec![angle("0")], .. cfg.clone() }, "rays_0.png");
+    let differs = |row: usize, x: usize| {
+        let p = 3 * (row * 41 + x);
+        plain[p..(p + 3)] != rays[p..(p + 3)]
+    };
+    // 4 wide, so 1/4 is in pixel 23
+    for x in 0..41 {
+        assert_eq!(differs(20, x), x >= 23, "pixel {} on the real line", x);
+        for &row in [0, 10, 19, 21, 30, 40].iter() {
+            assert!(!differs(row, x), "pixel {} of row {}", x, row);
+        }
+    }
+}
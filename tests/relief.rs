@@ -0,0 +1,54 @@
+extern crate fractals;
+use fractals::*;
+
+#[test]
+fn test_parse_relief() {
+    assert_eq!("".parse::<Relief>(), Ok(Relief::default()));
+    assert_eq!("135".parse::<Relief>(), Ok(Relief { angle: 135.0, .. Relief::default() }));
+    assert_eq!("90, 2, 0.5".parse::<Relief>(), Ok(Relief { angle: 90.0, height: 2.0, ambient: 0.5 }));
+    assert!("1,2,3,4".parse::<Relief>().is_err());
+    assert!("up".parse::<Relief>().is_err());
+}
+
+// a field rising to the right, 1 in log2(value + 1) across the height of the
+// image, with the inside on the last row
+fn ramp(width: usize, height: usize) -> Vec<f32> {
+    let mut value: Vec<f32> = (0..width * height)
+        .map(|i| 2f32.powf((i % width) as f32 / height as f32) - 1f32)
+        .collect();
+    for x in value[width * (height - 1)..].iter_mut() {
+        *x = -1f32;
+    }
+    value
+}
+
+#[test]
+fn test_relief_shade() {
+    let flat = Relief::default().shade(&vec![3f32; 20], 5);
+    let ambient = Relief::default().ambient as f32;
+    let up = ambient + (1f32 - ambient) * 0.5f32.sqrt();
+    assert!(flat.iter().all(|&x| (x - up).abs() < 1e-6));
+
+    let value = ramp(8, 6);
+    // facing left, away from a light on the right, and towards one on the
+    // left
+    let right = Relief { angle: 0.0, .. Relief::default() }.shade(&value, 8);
+    let left = Relief { angle: 180.0, .. Relief::default() }.shade(&value, 8);
+    for i in 0..8 * 5 {
+        assert!(right[i] < up && left[i] > up, "{} {}", right[i], left[i]);
+    }
+    // the inside is left alone
+    assert!(right[8 * 5..].iter().all(|&x| x == 1f32));
+    // the same slope at twice the size is lit the same
+    let twice = Relief { angle: 0.0, .. Relief::default() }.shade(&ramp(16, 12), 16);
+    assert!((twice[16 * 3 + 5] - right[8 + 2]).abs() < 1e-3);
+}
+
+#[test]
+fn test_relief_validation() {
+    let cfg = FractalCfg { relief: Some(Relief::default()), .. FractalCfg::default() };
+    assert!(cfg.validate().is_ok());
+    assert!(FractalCfg { formula: Formula::Lyapunov, .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { formula: Formula::Buddhabrot, .. cfg.clone() }.validate().is_err());
+    assert!(FractalCfg { relief: Some(Relief { ambient: 2.0, .. Relief::default() }), .. cfg }.validate().is_err());
+}
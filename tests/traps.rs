@@ -0,0 +1,76 @@
+extern crate fractals;
+use fractals::*;
+
+#[test]
+fn test_parse_trap() {
+    assert_eq!("point:0.5-0.5i".parse::<Trap>(), Ok(Trap::Point(0.5, -0.5)));
+    assert_eq!("circle:0,0.25".parse::<Trap>(), Ok(Trap::Circle(0.0, 0.0, 0.25)));
+    assert_eq!("cross:i".parse::<Trap>(), Ok(Trap::Cross(0.0, 1.0)));
+    assert_eq!("line:1,45".parse::<Trap>(), Ok(Trap::Line(1.0, 0.0, 45.0)));
+    assert!("circle:0".parse::<Trap>().is_err());
+    assert!("square:0".parse::<Trap>().is_err());
+}
+
+#[test]
+fn test_traps() {
+    let traps = [Trap::Point(0.0, 0.0), Trap::Circle(0.0, 0.0, 0.5), Trap::Cross(0.1, -0.1), Trap::Line(0.0, 0.0, 30.0)];
+    for &trap in traps.iter() {
+        let cfg = FractalCfg {
+            width: 61, height: 37, max_iterations: 100,
+            trap: Some(trap),
+            .. FractalCfg::default()
+        };
+        let scalar = mandelbrot_with_backend(&cfg, Backend::Scalar);
+        // every pixel has a distance, inside or not
+        assert!(scalar.iter().all(|&x| x >= 0f32 && x.is_finite()));
+        // and it's at most the distance of the first point, which is the pixel
+        let (w, h) = (cfg.width as usize, cfg.height as usize);
+        let corner = (2f32 * w as f32 / h as f32).hypot(2f32);
+        assert!(scalar.iter().all(|&x| x <= corner + 1f32));
+        for backend in Backend::available() {
+            let buf = mandelbrot_with_backend(&cfg, backend);
+            // only fma rounds differently
+            if backend != Backend::Avx2Fma {
+                assert!(buf == scalar, "{:?} {:?} differs from scalar", trap, backend);
+                continue;
+            }
+            let n_diff = scalar.iter().zip(buf.iter())
+                .filter(|&(a, b)| (a - b).abs() > 1e-3)
+                .count();
+            assert!(n_diff * 50 < buf.len(), "{:?} {:?} differs on {} pixels", trap, backend, n_diff);
+        }
+    }
+}
+
+#[test]
+fn test_point_trap_matches_orbit() {
+    let cfg = FractalCfg {
+        width: 41, height: 41, max_iterations: 50,
+        // a trap outside the bailout, which orbits only get near if they're
+        // iterated past it
+        bailout: 2.0,
+        trap: Some(Trap::Point(3.0, 0.0)),
+        precision: Precision::Double,
+        .. FractalCfg::default()
+    };
+    let buf = mandelbrot(&cfg);
+    let (w, h) = (cfg.width as usize, cfg.height as usize);
+    let threshold = cfg.bailout * cfg.bailout;
+    for &(x, row) in [(0, 0), (20, 20), (10, 30), (33, 7), (25, 15)].iter() {
+        let (cr, ci) = (-2.0 + 4.0 * x as f64 / w as f64, -2.0 + 4.0 * (h - row - 1) as f64 / h as f64);
+        let (mut zr, mut zi) = (cr, ci);
+        let mut closest = (zr - 3.0).hypot(zi);
+        for _ in 0..cfg.max_iterations {
+            let r = zr * zr - zi * zi + cr;
+            zi = 2.0 * zr * zi + ci;
+            zr = r;
+            if zr * zr + zi * zi >= threshold {
+                break;
+            }
+            closest = closest.min((zr - 3.0).hypot(zi));
+        }
+        let got = buf[row*w + x] as f64;
+        assert!((got - closest).abs() < 1e-6, "pixel {},{}: {} vs {}", x, row, got, closest);
+    }
+    assert!(FractalCfg { formula: Formula::Newton, .. cfg }.validate().is_err());
+}